@@ -1,32 +1,134 @@
-use log::{debug, error, info};
+use std::collections::HashSet;
+
+use log::error;
 
 use crate::{
     error::{Code, PossibleErrors},
-    tokens::{Keyword, Token, Type},
+    parser::{closing_bracket, closing_parenthesis, shunting_yard, split_arguments, Postfix},
+    span::{Span, Spanned},
+    tokens::{identifier_key, Keyword, Token, Type},
 };
 
+/// An expression in postfix order, built while parsing so a malformed one is reported
+/// along with every other syntax error. Each item keeps where it was written, so errors
+/// while evaluating it can point there.
+pub type Expression = Vec<Spanned<Postfix>>;
+
+/// A variable being written to, `indices` holds one expression per dimension
+/// when it's an array element, and is empty otherwise.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub name: String,
+    pub indices: Vec<Expression>,
+}
+
+/// One labelled branch of a Segun, `labels` holds each comma separated value.
+#[derive(Debug, Clone)]
+pub struct SwitchCase {
+    pub labels: Vec<Expression>,
+    pub code: Block,
+}
+
+/// How a parameter receives its argument. Without `Por Valor` or `Por Referencia`
+/// arrays are passed by reference and everything else by value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PassBy {
+    Default,
+    Value,
+    Reference,
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub pass_by: PassBy,
+}
+
+/// A `SubProceso` or `Funcion`, `return_variable` is the one in `SubProceso r <- Nombre(...)`.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub return_variable: Option<String>,
+    pub code: Block,
+}
+
+/// A whole source file: the main algorithm and every subproceso declared around it.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub algorithm: Block,
+    pub functions: Vec<Function>,
+}
+
+/// The statements inside a block, each with the line it was written on.
+pub type Block = Vec<Spanned<ASTNode>>;
+
 #[derive(Debug, Clone)]
 pub enum ASTNode {
     VariableDeclaration {
         names: Vec<String>,
         var_type: Type,
     },
+    /// `arrays` holds the name of every array and the size of each of its dimensions.
+    DimensionStatement {
+        arrays: Vec<Target>,
+    },
     Assignment {
-        name: String,
-        expression: Vec<Token>,
+        target: Target,
+        expression: Expression,
     },
     WriteStatement {
-        expressions: Vec<Token>,
+        expressions: Vec<Expression>,
+        newline: bool,
     },
     ReadStatement {
-        variables: Vec<String>,
+        variables: Vec<Target>,
     },
     IfStatement {
-        condition: Vec<Token>,
-        code: Vec<Vec<Token>>,
+        condition: Expression,
+        code: Block,
+        else_code: Block,
+    },
+    WhileStatement {
+        condition: Expression,
+        code: Block,
+    },
+    /// `until` is true for Hasta Que (exit when the condition holds),
+    /// false for Mientras Que (keep going while it holds).
+    RepeatStatement {
+        code: Block,
+        condition: Expression,
+        until: bool,
+    },
+    ForStatement {
+        variable: String,
+        start: Expression,
+        end: Expression,
+        step: Option<Expression>,
+        code: Block,
+    },
+    ForEachStatement {
+        variable: String,
+        array: String,
+        code: Block,
+    },
+    SwitchStatement {
+        selector: Expression,
+        cases: Vec<SwitchCase>,
+        default: Block,
+    },
+    CallStatement {
+        name: String,
+        arguments: Vec<Expression>,
     },
 }
 
+/// The tokens of `tokens` without their spans, to match a line against a pattern.
+/// The pattern's positions are the same ones in `tokens`.
+fn nodes(tokens: &[Spanned<Token>]) -> Vec<Token> {
+    tokens.iter().map(|token| token.node.clone()).collect()
+}
+
 fn parse_variable_declaration(tokens: &[Token]) -> Result<(Vec<String>, Type), Code> {
     if tokens.len() < 3 {
         return Err(Code {
@@ -35,10 +137,8 @@ fn parse_variable_declaration(tokens: &[Token]) -> Result<(Vec<String>, Type), C
     }
 
     // último token debe ser Tipo, penúltimo debe ser Instruccion(Como)
-    if let (Some(Token::Instruccion(Keyword::Como)), Some(Token::Tipo(t))) =
-        (tokens.get(tokens.len() - 2), tokens.get(tokens.len() - 1))
-    {
-        let vars = tokens[..tokens.len() - 2]
+    if let [vars @ .., Token::Instruccion(Keyword::Como), Token::Tipo(t)] = tokens {
+        let vars = vars
             .iter()
             .filter_map(|t| {
                 if let Token::Variable(name) = t {
@@ -49,7 +149,7 @@ fn parse_variable_declaration(tokens: &[Token]) -> Result<(Vec<String>, Type), C
             })
             .collect();
 
-        Ok((vars, t.clone()))
+        Ok((vars, *t))
     } else {
         Err(Code {
             error: PossibleErrors::MissingTypeOrUnvalidType,
@@ -57,112 +157,1386 @@ fn parse_variable_declaration(tokens: &[Token]) -> Result<(Vec<String>, Type), C
     }
 }
 
-fn extract_condition(tokens: &[Token]) -> Result<Vec<Token>, Code> {
-    if let [Token::AbrirParentesis, middle @ .., Token::CerrarParentesis, Token::Instruccion(Keyword::Entonces)] =
-        tokens
-    {
-        Ok(middle.to_vec())
-    } else {
-        Err(Code {
+/// Whether `token` is the Sin Saltar or Sin Bajar modifier of an Escribir.
+fn is_write_modifier(token: &Spanned<Token>) -> bool {
+    matches!(
+        token.node,
+        Token::Instruccion(Keyword::SinSaltar | Keyword::SinBajar)
+    )
+}
+
+/// Parses the `(a, b)` after a subproceso's name, `tokens` must start at the parenthesis.
+/// A missing or empty list means no arguments.
+fn parse_argument_list(tokens: &[Spanned<Token>]) -> Result<Vec<Vec<Spanned<Token>>>, Code> {
+    let Some((open, rest)) = tokens.split_first() else {
+        return Ok(Vec::new());
+    };
+    if open.node != Token::AbrirParentesis {
+        return Err(Code {
             error: PossibleErrors::SyntaxError,
-        })
+        });
+    }
+
+    match closing_parenthesis(rest) {
+        Some(close) if close + 1 == rest.len() => {
+            let inner = &rest[..close];
+            if inner.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let arguments = split_arguments(inner);
+            if arguments.iter().any(Vec::is_empty) {
+                return Err(Code {
+                    error: PossibleErrors::MissingExpression,
+                });
+            }
+
+            Ok(arguments)
+        }
+        Some(_) => Err(Code {
+            error: PossibleErrors::SyntaxError,
+        }),
+        None => Err(Code {
+            error: PossibleErrors::MissingClosingParenthesis,
+        }),
     }
 }
 
-pub fn build_ast(code: Vec<Vec<Token>>) -> Result<Vec<ASTNode>, Code> {
-    let mut ast = Vec::new();
-    let mut i = 0;
+/// Parses the `r <- Nombre(a, b)`, `Nombre(a, b)` or `Nombre` after the opening keyword.
+fn parse_function_header(
+    tokens: &[Spanned<Token>],
+) -> Result<(String, Option<String>, Vec<Parameter>), Code> {
+    let (return_variable, name, rest) = match nodes(tokens).as_slice() {
+        [Token::Variable(variable), Token::Igual, Token::Variable(name), ..] => {
+            (Some(variable.clone()), name.clone(), &tokens[3..])
+        }
+        [Token::Variable(name), ..] => (None, name.clone(), &tokens[1..]),
+        _ => {
+            return Err(Code {
+                error: PossibleErrors::SyntaxError,
+            })
+        }
+    };
 
-    while i < code.len() {
-        let line = &code[i];
+    let parameters = parse_argument_list(rest)?
+        .into_iter()
+        .map(|parameter| {
+            let (name, pass_by) = match nodes(&parameter).as_slice() {
+                [Token::Variable(name)] => (name.clone(), PassBy::Default),
+                [Token::Variable(name), Token::Instruccion(Keyword::PorValor)] => {
+                    (name.clone(), PassBy::Value)
+                }
+                [Token::Variable(name), Token::Instruccion(Keyword::PorReferencia)] => {
+                    (name.clone(), PassBy::Reference)
+                }
+                _ => {
+                    return Err(Code {
+                        error: PossibleErrors::SyntaxError,
+                    })
+                }
+            };
 
-        match line.as_slice() {
-            // Definir a, b, c Como Entero
-            [Token::Instruccion(Keyword::Definir), rest @ ..] => {
-                let (vars, tipo) = parse_variable_declaration(rest)?;
-                ast.push(ASTNode::VariableDeclaration {
-                    names: vars,
-                    var_type: tipo,
+            Ok(Parameter { name, pass_by })
+        })
+        .collect::<Result<Vec<Parameter>, Code>>()?;
+
+    Ok((name, return_variable, parameters))
+}
+
+/// Extracts the condition between the opening keyword and `closing` (Entonces, Hacer).
+fn extract_condition(
+    tokens: &[Spanned<Token>],
+    closing: Keyword,
+) -> Result<Vec<Spanned<Token>>, Code> {
+    match tokens.split_last() {
+        Some((last, middle)) if last.node == Token::Instruccion(closing) => {
+            if middle.is_empty() {
+                return Err(Code {
+                    error: PossibleErrors::MissingExpression,
                 });
             }
+            Ok(middle.to_vec())
+        }
+        _ => Err(Code {
+            error: PossibleErrors::MissingKeyword(closing.to_string()),
+        }),
+    }
+}
 
-            // nombre <- expresión
-            [Token::Variable(var), Token::Igual, rest @ ..] => {
-                ast.push(ASTNode::Assignment {
-                    name: var.clone(),
-                    expression: rest.to_vec(),
-                });
+/// Keywords that start a new top level block, no statement inside another block can start with them.
+const TOP_LEVEL: [Keyword; 3] = [Keyword::Algoritmo, Keyword::SubProceso, Keyword::Funcion];
+
+/// The `variable <- start Hasta end Con Paso step Hacer` part of a Para, before turning
+/// the bounds into expressions.
+#[derive(Default)]
+struct ForHeader {
+    variable: String,
+    start: Vec<Spanned<Token>>,
+    end: Vec<Spanned<Token>>,
+    step: Option<Vec<Spanned<Token>>>,
+}
+
+fn parse_for_header(tokens: &[Spanned<Token>]) -> Result<ForHeader, Code> {
+    let header = nodes(tokens);
+    let [Token::Variable(variable), Token::Igual, ..] = header.as_slice() else {
+        return Err(Code {
+            error: PossibleErrors::SyntaxError,
+        });
+    };
+    let bounds = extract_condition(&tokens[2..], Keyword::Hacer)?;
+
+    let Some(hasta) = bounds
+        .iter()
+        .position(|t| t.node == Token::Instruccion(Keyword::Hasta))
+    else {
+        return Err(Code {
+            error: PossibleErrors::MissingKeyword(Keyword::Hasta.to_string()),
+        });
+    };
+    let (start, end) = (&bounds[..hasta], &bounds[hasta + 1..]);
+
+    let (end, step) = match end
+        .iter()
+        .position(|t| t.node == Token::Instruccion(Keyword::ConPaso))
+    {
+        Some(con_paso) => (&end[..con_paso], Some(end[con_paso + 1..].to_vec())),
+        None => (end, None),
+    };
+
+    if start.is_empty() || end.is_empty() || step.as_ref().is_some_and(Vec::is_empty) {
+        return Err(Code {
+            error: PossibleErrors::MissingExpression,
+        });
+    }
+
+    Ok(ForHeader {
+        variable: variable.clone(),
+        start: start.to_vec(),
+        end: end.to_vec(),
+        step,
+    })
+}
+
+/// Reads the `elemento De arreglo Hacer` part of a Para Cada.
+fn parse_for_each_header(tokens: &[Spanned<Token>]) -> Result<(String, String), Code> {
+    let header = nodes(&extract_condition(tokens, Keyword::Hacer)?);
+    let [Token::Variable(variable), Token::Instruccion(Keyword::De), Token::Variable(array)] =
+        header.as_slice()
+    else {
+        return Err(Code {
+            error: PossibleErrors::SyntaxError,
+        });
+    };
+
+    Ok((variable.clone(), array.clone()))
+}
+
+/// Walks the lexed lines and builds the statement tree, one block per call to `parse_block`.
+/// Syntax errors are collected as they're found and parsing goes on from the next statement.
+struct AstBuilder {
+    lines: Vec<Vec<Spanned<Token>>>,
+    position: usize,
+    /// The line of the innermost statement being parsed, errors are reported there.
+    span: Span,
+    /// Terminators of every block being parsed, innermost last.
+    open: Vec<Keyword>,
+    /// Names declared with `Definir` in the algorithm or subproceso being parsed, declaring
+    /// one again is an error even when the first one is inside a loop.
+    declared: HashSet<String>,
+    errors: Vec<Spanned<Code>>,
+}
+
+impl AstBuilder {
+    fn new(lines: Vec<Vec<Spanned<Token>>>) -> AstBuilder {
+        Self {
+            lines,
+            position: 0,
+            span: Span::default(),
+            open: Vec::new(),
+            declared: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Moves on to the next line, making it the one errors are reported at.
+    fn advance(&mut self) -> Option<Vec<Spanned<Token>>> {
+        let line = self.lines.get(self.position)?.clone();
+
+        if let (Some(first), Some(last)) = (line.first(), line.last()) {
+            self.span = first.span.to(last.span);
+        }
+        self.position += 1;
+
+        Some(line)
+    }
+
+    /// Records an error at the line being parsed.
+    fn error(&mut self, error: PossibleErrors) {
+        self.errors.push(Spanned::new(Code { error }, self.span));
+    }
+
+    /// Records the error in `result` if there is one and goes on with a default value,
+    /// so the block opened by a statement with a broken first line still gets parsed.
+    fn recover<T: Default>(&mut self, result: Result<T, Code>) -> T {
+        result.unwrap_or_else(|code| {
+            self.errors.push(Spanned::new(code, self.span));
+            T::default()
+        })
+    }
+
+    /// Turns the tokens of an expression into postfix, recording the error where it
+    /// happened if they are malformed. Missing expressions are reported by whoever
+    /// expected them, so nothing is recorded for those.
+    fn expression(&mut self, tokens: &[Spanned<Token>]) -> Expression {
+        if tokens.is_empty() {
+            return Vec::new();
+        }
+
+        shunting_yard(tokens).unwrap_or_else(|error| {
+            self.errors.push(error);
+            Vec::new()
+        })
+    }
+
+    /// Parses `nombre` or `nombre[i, j]` at the start of `tokens`, returning what comes after it.
+    fn parse_target<'a>(
+        &mut self,
+        tokens: &'a [Spanned<Token>],
+    ) -> Result<(Target, &'a [Spanned<Token>]), Code> {
+        match nodes(tokens).as_slice() {
+            [Token::Variable(name), Token::AbrirCorchete, ..] => {
+                let rest = &tokens[2..];
+                let Some(close) = closing_bracket(rest) else {
+                    return Err(Code {
+                        error: PossibleErrors::SyntaxError,
+                    });
+                };
+
+                let indices = split_arguments(&rest[..close]);
+                if indices.iter().any(Vec::is_empty) {
+                    return Err(Code {
+                        error: PossibleErrors::SyntaxError,
+                    });
+                }
+
+                let target = Target {
+                    name: name.clone(),
+                    indices: indices.iter().map(|index| self.expression(index)).collect(),
+                };
+                Ok((target, &rest[close + 1..]))
+            }
+            [Token::Variable(name), ..] => {
+                let target = Target {
+                    name: name.clone(),
+                    indices: Vec::new(),
+                };
+                Ok((target, &tokens[1..]))
             }
+            _ => Err(Code {
+                error: PossibleErrors::SyntaxError,
+            }),
+        }
+    }
 
-            // Escribir a, "hola", b
-            [Token::Instruccion(Keyword::Escribir), rest @ ..] => {
-                ast.push(ASTNode::WriteStatement {
-                    expressions: rest
-                        .iter()
-                        .map(|f| {
-                            if f == &Token::SeparadorArgumento {
-                                Token::Suma
-                            } else {
-                                f.clone()
-                            }
-                        })
-                        .collect(),
-                });
+    /// Parses the arguments of an Escribir. The Sin Saltar or Sin Bajar modifier only stops
+    /// the line from ending, it can be an argument on its own or go right at the start or the
+    /// end of the list, anywhere else it's a syntax error.
+    fn parse_write(&mut self, tokens: &[Spanned<Token>]) -> Result<ASTNode, Code> {
+        let mut newline = true;
+
+        let mut expressions: Vec<Vec<Spanned<Token>>> = split_arguments(tokens)
+            .into_iter()
+            .filter(|expression| {
+                let alone = matches!(expression.as_slice(), [token] if is_write_modifier(token));
+                newline &= !alone;
+                !alone
+            })
+            .collect();
+
+        if let Some(first) = expressions.first_mut() {
+            if first.first().is_some_and(is_write_modifier) {
+                first.remove(0);
+                newline = false;
             }
+        }
+        if let Some(last) = expressions.last_mut() {
+            if last.last().is_some_and(is_write_modifier) {
+                last.pop();
+                newline = false;
+            }
+        }
 
-            // Leer a, b
-            [Token::Instruccion(Keyword::Leer), rest @ ..] => {
-                let vars = rest
-                    .iter()
-                    .filter_map(|t| {
-                        if let Token::Variable(name) = t {
-                            Some(name.clone())
-                        } else {
-                            None
-                        }
+        if expressions.is_empty() || expressions.iter().any(Vec::is_empty) {
+            return Err(Code {
+                error: PossibleErrors::MissingExpression,
+            });
+        }
+        if expressions.iter().flatten().any(is_write_modifier) {
+            return Err(Code {
+                error: PossibleErrors::SyntaxError,
+            });
+        }
+
+        Ok(ASTNode::WriteStatement {
+            expressions: expressions
+                .iter()
+                .map(|expression| self.expression(expression))
+                .collect(),
+            newline,
+        })
+    }
+
+    /// Parses a comma separated list of targets, like the ones in Leer and Dimension.
+    fn parse_target_list(&mut self, tokens: &[Spanned<Token>]) -> Result<Vec<Target>, Code> {
+        let mut targets = Vec::new();
+
+        for argument in split_arguments(tokens) {
+            match self.parse_target(&argument)? {
+                (target, []) => targets.push(target),
+                _ => {
+                    return Err(Code {
+                        error: PossibleErrors::SyntaxError,
                     })
-                    .collect();
-                ast.push(ASTNode::ReadStatement { variables: vars });
+                }
+            }
+        }
+
+        Ok(targets)
+    }
+
+    /// Whether `line` closes one of the first `depth` open blocks or starts a new top level
+    /// one, meaning every block opened after them was left unclosed.
+    fn leaves_block(&self, line: &[Spanned<Token>], depth: usize) -> bool {
+        match line.first().map(|token| &token.node) {
+            Some(Token::Instruccion(keyword)) => {
+                self.open[..depth].contains(keyword) || TOP_LEVEL.contains(keyword)
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses statements until one of `terminators` starts a line, consuming that line.
+    /// Returns the line that closed the block, or `None` if the input ran out or an
+    /// outer block was closed first.
+    fn parse_block(&mut self, terminators: &[Keyword]) -> (Block, Option<Vec<Spanned<Token>>>) {
+        let depth = self.open.len();
+        self.open.extend_from_slice(terminators);
+
+        let mut nodes = Vec::new();
+        let mut closing = None;
+
+        while let Some(line) = self.lines.get(self.position) {
+            if let Some(Token::Instruccion(keyword)) = line.first().map(|token| &token.node) {
+                if terminators.contains(keyword) {
+                    closing = self.advance();
+                    break;
+                }
+            }
+
+            if self.leaves_block(line, depth) {
+                break;
+            }
+
+            nodes.extend(self.parse_statement());
+        }
+
+        self.open.truncate(depth);
+        (nodes, closing)
+    }
+
+    /// Parses a nested block that must be closed by a line holding only `end`, opened by `start`.
+    fn parse_closed_block(&mut self, start: Keyword, end: Keyword) -> Block {
+        let (nodes, closing) = self.parse_block(&[end]);
+
+        match closing {
+            Some(line) if line.len() == 1 => {}
+            Some(_) => self.error(PossibleErrors::SyntaxError),
+            None => self.error(PossibleErrors::MissingBlockEnd(start.to_string())),
+        }
+
+        nodes
+    }
+
+    /// Puts `tokens` back as the next line to parse, for keywords that can share
+    /// their line with another statement.
+    fn push_back(&mut self, tokens: Vec<Spanned<Token>>) {
+        self.position -= 1;
+        self.lines[self.position] = tokens;
+    }
+
+    // Si (...) Entonces
+    // ...
+    // Sino
+    // ...
+    // FinSi
+    fn parse_if(&mut self, rest: &[Spanned<Token>]) -> Result<ASTNode, Code> {
+        let condition = self.recover(extract_condition(rest, Keyword::Entonces));
+        let condition = self.expression(&condition);
+
+        let (code, closing) = self.parse_block(&[Keyword::Sino, Keyword::FinSi]);
+        let else_code = match closing.as_deref() {
+            Some([keyword]) if keyword.node == Token::Instruccion(Keyword::FinSi) => Vec::new(),
+            Some([keyword, rest @ ..]) if keyword.node == Token::Instruccion(Keyword::Sino) => {
+                if !rest.is_empty() {
+                    // Sino Si (...) Entonces: the rest of the line opens the else branch
+                    self.push_back(rest.to_vec());
+                }
+                self.parse_closed_block(Keyword::Si, Keyword::FinSi)
+            }
+            Some(_) => {
+                return Err(Code {
+                    error: PossibleErrors::SyntaxError,
+                })
+            }
+            None => {
+                return Err(Code {
+                    error: PossibleErrors::MissingBlockEnd(Keyword::Si.to_string()),
+                })
+            }
+        };
+
+        Ok(ASTNode::IfStatement {
+            condition,
+            code,
+            else_code,
+        })
+    }
+
+    // Mientras (...) Hacer
+    // ...
+    // FinMientras
+    fn parse_while(&mut self, rest: &[Spanned<Token>]) -> ASTNode {
+        let condition = self.recover(extract_condition(rest, Keyword::Hacer));
+        let condition = self.expression(&condition);
+        let code = self.parse_closed_block(Keyword::Mientras, Keyword::FinMientras);
+
+        ASTNode::WhileStatement { condition, code }
+    }
+
+    // Repetir
+    // ...
+    // Hasta Que (...) | Mientras Que (...)
+    fn parse_repeat(&mut self) -> Result<ASTNode, Code> {
+        let (code, closing) = self.parse_block(&[Keyword::HastaQue, Keyword::MientrasQue]);
+
+        match closing.as_deref() {
+            Some([keyword, condition @ ..]) if !condition.is_empty() => {
+                Ok(ASTNode::RepeatStatement {
+                    code,
+                    condition: self.expression(condition),
+                    until: keyword.node == Token::Instruccion(Keyword::HastaQue),
+                })
             }
+            Some(_) => Err(Code {
+                error: PossibleErrors::MissingExpression,
+            }),
+            None => Err(Code {
+                error: PossibleErrors::MissingBlockEnd(Keyword::Repetir.to_string()),
+            }),
+        }
+    }
+
+    // Para i <- (...) Hasta (...) Con Paso (...) Hacer
+    // ...
+    // FinPara
+    fn parse_for(&mut self, rest: &[Spanned<Token>]) -> ASTNode {
+        let ForHeader {
+            variable,
+            start,
+            end,
+            step,
+        } = self.recover(parse_for_header(rest));
+        let start = self.expression(&start);
+        let end = self.expression(&end);
+        let step = step.map(|step| self.expression(&step));
+        let code = self.parse_closed_block(Keyword::Para, Keyword::FinPara);
 
-            // Si (...) Entonces
-            [Token::Instruccion(Keyword::Si), rest @ ..] => {
-                let condition = extract_condition(rest)?;
-                let mut if_code = Vec::new();
+        ASTNode::ForStatement {
+            variable,
+            start,
+            end,
+            step,
+            code,
+        }
+    }
+
+    // Para Cada elemento De arreglo Hacer
+    // ...
+    // FinPara
+    fn parse_for_each(&mut self, rest: &[Spanned<Token>]) -> ASTNode {
+        let (variable, array) = self.recover(parse_for_each_header(rest));
+        let code = self.parse_closed_block(Keyword::ParaCada, Keyword::FinPara);
+
+        ASTNode::ForEachStatement {
+            variable,
+            array,
+            code,
+        }
+    }
 
-                i += 1; // move to the next line after Si
+    // Segun (...) Hacer
+    //     1, 2: ...
+    //     De Otro Modo: ...
+    // FinSegun
+    fn parse_switch(&mut self, rest: &[Spanned<Token>]) -> Result<ASTNode, Code> {
+        let selector = self.recover(extract_condition(rest, Keyword::Hacer));
+        let selector = self.expression(&selector);
+        let opening = self.span;
+        let mut cases = Vec::new();
+        let mut default = Vec::new();
 
-                while i < code.len() {
-                    if code[i] == [Token::Instruccion(Keyword::FinSi)] {
-                        break;
+        let depth = self.open.len();
+        self.open.extend([Keyword::DeOtroModo, Keyword::FinSegun]);
+
+        let closed = loop {
+            let ended = self
+                .lines
+                .get(self.position)
+                .is_none_or(|line| self.leaves_block(line, depth));
+            if ended {
+                break false;
+            }
+            let line = self.advance().unwrap_or_default();
+
+            match nodes(&line).as_slice() {
+                [Token::Instruccion(Keyword::FinSegun)] => break true,
+
+                [Token::Instruccion(Keyword::DeOtroModo), Token::DosPuntos, rest @ ..] => {
+                    if !rest.is_empty() {
+                        self.push_back(line[2..].to_vec());
                     }
-                    if_code.push(code[i].clone());
-                    i += 1;
+                    self.open.truncate(depth);
+                    default = self.parse_closed_block(Keyword::Segun, Keyword::FinSegun);
+                    break true;
                 }
 
-                ast.push(ASTNode::IfStatement {
-                    condition,
-                    code: if_code,
-                });
+                _ => {
+                    let colon = line.iter().position(|t| t.node == Token::DosPuntos);
+
+                    let labels: Vec<&[Spanned<Token>]> = line[..colon.unwrap_or(line.len())]
+                        .split(|t| t.node == Token::SeparadorArgumento)
+                        .collect();
+                    if colon.is_none() || labels.iter().any(|label| label.is_empty()) {
+                        self.error(PossibleErrors::SyntaxError);
+                    }
+                    let labels = labels
+                        .into_iter()
+                        .map(|label| self.expression(label))
+                        .collect();
+
+                    if let Some(colon) = colon.filter(|colon| colon + 1 < line.len()) {
+                        self.push_back(line[colon + 1..].to_vec());
+                    }
+                    let code = self.parse_case_body(depth);
+
+                    cases.push(SwitchCase { labels, code });
+                }
+            }
+        };
+        self.open.truncate(depth);
+
+        if !closed {
+            self.span = opening;
+            return Err(Code {
+                error: PossibleErrors::MissingBlockEnd(Keyword::Segun.to_string()),
+            });
+        }
+
+        Ok(ASTNode::SwitchStatement {
+            selector,
+            cases,
+            default,
+        })
+    }
+
+    /// Parses the statements of a Segun branch, up to the next label or the end of the block.
+    /// `depth` is the amount of blocks open around the Segun.
+    fn parse_case_body(&mut self, depth: usize) -> Block {
+        let mut nodes = Vec::new();
+
+        while let Some(line) = self.lines.get(self.position) {
+            let ends_case = line.iter().any(|t| t.node == Token::DosPuntos)
+                || matches!(
+                    line.first().map(|token| &token.node),
+                    Some(Token::Instruccion(Keyword::DeOtroModo | Keyword::FinSegun))
+                )
+                || self.leaves_block(line, depth);
+            if ends_case {
+                break;
+            }
+
+            nodes.extend(self.parse_statement());
+        }
+
+        nodes
+    }
+
+    /// Parses the statement on the next line along with any block it opens, recording
+    /// the error instead if it can't be built.
+    fn parse_statement(&mut self) -> Option<Spanned<ASTNode>> {
+        let outer = self.span;
+        let line = self.advance().unwrap_or_default();
+        let span = self.span;
+
+        let node = match self.parse_line(&line) {
+            Ok(node) => Some(Spanned::new(node, span)),
+            Err(code) => {
+                self.errors.push(Spanned::new(code, self.span));
+                None
+            }
+        };
+        self.span = outer;
+
+        node
+    }
+
+    fn parse_line(&mut self, line: &[Spanned<Token>]) -> Result<ASTNode, Code> {
+        let rest = line.get(1..).unwrap_or_default();
+
+        match nodes(line).as_slice() {
+            // Definir a, b, c Como Entero
+            [Token::Instruccion(Keyword::Definir), tokens @ ..] => {
+                let (vars, tipo) = parse_variable_declaration(tokens)?;
+                if let Some(name) = vars
+                    .iter()
+                    .find(|name| !self.declared.insert(identifier_key(name)))
+                {
+                    return Err(Code {
+                        error: PossibleErrors::DuplicateDeclaration(name.clone()),
+                    });
+                }
+
+                Ok(ASTNode::VariableDeclaration {
+                    names: vars,
+                    var_type: tipo,
+                })
+            }
+
+            // Dimension v[10], m[3, 4]
+            [Token::Instruccion(Keyword::Dimension), ..] => {
+                let arrays = self.parse_target_list(rest)?;
+                if arrays.iter().any(|array| array.indices.is_empty()) {
+                    return Err(Code {
+                        error: PossibleErrors::SyntaxError,
+                    });
+                }
 
-                // skip the FinSi line
-                i += 1;
-                continue; // skip incrementing i again at the end of the loop
+                Ok(ASTNode::DimensionStatement { arrays })
             }
 
-            // FinSi already handled inside the If block
-            [Token::Instruccion(Keyword::FinSi)] => {}
+            // Nombre(a, b)
+            [Token::Variable(name), tokens @ ..]
+                if tokens.is_empty() || tokens.first() == Some(&Token::AbrirParentesis) =>
+            {
+                let arguments = parse_argument_list(rest)?
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect();
+
+                Ok(ASTNode::CallStatement {
+                    name: name.clone(),
+                    arguments,
+                })
+            }
+
+            // nombre <- expresión
+            // nombre[i, j] <- expresión
+            [Token::Variable(_), ..] => match self.parse_target(line)? {
+                (target, [igual, expression @ ..])
+                    if igual.node == Token::Igual && !expression.is_empty() =>
+                {
+                    Ok(ASTNode::Assignment {
+                        target,
+                        expression: self.expression(expression),
+                    })
+                }
+                _ => Err(Code {
+                    error: PossibleErrors::IncompleteAssignment,
+                }),
+            },
+
+            // Escribir a, "hola", b
+            // every expression is written on its own, whatever its type
+            [Token::Instruccion(Keyword::Escribir), ..] => self.parse_write(rest),
+
+            // Leer a, v[i]
+            [Token::Instruccion(Keyword::Leer), ..] => Ok(ASTNode::ReadStatement {
+                variables: self.parse_target_list(rest)?,
+            }),
+
+            [Token::Instruccion(Keyword::Si), ..] => self.parse_if(rest),
+            [Token::Instruccion(Keyword::Mientras), ..] => Ok(self.parse_while(rest)),
+            [Token::Instruccion(Keyword::Repetir)] => self.parse_repeat(),
+            [Token::Instruccion(Keyword::Para), ..] => Ok(self.parse_for(rest)),
+
+            [Token::Instruccion(Keyword::ParaCada), ..] => Ok(self.parse_for_each(rest)),
+            [Token::Instruccion(Keyword::Segun), ..] => self.parse_switch(rest),
+
+            // a block terminator that no open block is waiting for
+            [Token::Instruccion(
+                keyword @ (Keyword::Sino
+                | Keyword::FinSi
+                | Keyword::FinMientras
+                | Keyword::HastaQue
+                | Keyword::MientrasQue
+                | Keyword::FinPara
+                | Keyword::DeOtroModo
+                | Keyword::FinSegun
+                | Keyword::FinAlgoritmo
+                | Keyword::FinSubProceso
+                | Keyword::FinFuncion),
+            ), ..] => Err(Code {
+                error: PossibleErrors::UnexpectedBlockEnd(keyword.to_string()),
+            }),
 
             err => {
-                error!("Instruction that gave the error: {:?}", err);
-                return Err(Code {
+                error!("Instruction that gave the error: {err:?}");
+                Err(Code {
                     error: PossibleErrors::SyntaxError,
-                });
+                })
             }
         }
+    }
+}
+
+impl AstBuilder {
+    // SubProceso r <- Nombre(a, b)
+    // ...
+    // FinSubProceso
+    fn parse_function(&mut self, start: Keyword, rest: &[Spanned<Token>]) -> Function {
+        let (name, return_variable, parameters) = self.recover(parse_function_header(rest));
+
+        let end = if start == Keyword::Funcion {
+            Keyword::FinFuncion
+        } else {
+            Keyword::FinSubProceso
+        };
+        self.declared.clear();
+        let code = self.parse_closed_block(start, end);
+
+        Function {
+            name,
+            parameters,
+            return_variable,
+            code,
+        }
+    }
+
+    /// Parses the top level of a file, where only the algorithm and subprocesos can appear.
+    fn parse_program(&mut self) -> Program {
+        let mut algorithm = None;
+        let mut functions = Vec::new();
+
+        while let Some(line) = self.advance() {
+            match nodes(&line).as_slice() {
+                [Token::Instruccion(Keyword::Algoritmo), Token::Variable(_)] => {
+                    if algorithm.is_some() {
+                        self.error(PossibleErrors::DuplicateAlgorithm);
+                    }
+
+                    self.declared.clear();
+                    let code = self.parse_closed_block(Keyword::Algoritmo, Keyword::FinAlgoritmo);
+                    algorithm.get_or_insert(code);
+                }
+                [Token::Instruccion(start @ (Keyword::SubProceso | Keyword::Funcion)), ..] => {
+                    let header = self.span;
+                    let function = self.parse_function(*start, &line[1..]);
+
+                    let key = identifier_key(&function.name);
+                    let duplicate = functions
+                        .iter()
+                        .any(|other: &Function| identifier_key(&other.name) == key);
+                    if duplicate {
+                        let error = PossibleErrors::DuplicateFunction(function.name.clone());
+                        self.errors.push(Spanned::new(Code { error }, header));
+                    }
+
+                    functions.push(function);
+                }
+                tokens => {
+                    error!("Instruction outside of the algorithm: {tokens:?}");
+                    self.error(PossibleErrors::OutsideAlgorithm);
+                }
+            }
+        }
+
+        if algorithm.is_none() {
+            self.error(PossibleErrors::MissingAlgorithm);
+        }
 
-        i += 1; // only increment if not inside a special block
+        Program {
+            algorithm: algorithm.unwrap_or_default(),
+            functions,
+        }
+    }
+}
+
+/// Builds the statements of a single block, without the Algoritmo around them.
+#[cfg(test)]
+pub fn build_ast(code: Vec<Vec<Spanned<Token>>>) -> Result<Block, Code> {
+    let mut builder = AstBuilder::new(code);
+    let (ast, _) = builder.parse_block(&[]);
+
+    match builder.errors.into_iter().next() {
+        Some(error) => Err(error.node),
+        None => Ok(ast),
+    }
+}
+
+/// Builds the program from the lexed lines. If anything is wrong, every syntax error
+/// found is returned in the order they appear, each with the line it was found on.
+pub fn build_program(code: Vec<Vec<Spanned<Token>>>) -> Result<Program, Vec<Spanned<Code>>> {
+    let mut builder = AstBuilder::new(code);
+    let program = builder.parse_program();
+
+    if builder.errors.is_empty() {
+        return Ok(program);
+    }
+
+    let mut errors = builder.errors;
+    errors.sort_by_key(|error| error.span.start);
+    Err(errors)
+}
+
+#[cfg(test)]
+mod ast_tests {
+    use crate::{
+        lexer::{split_lines, Lexer},
+        parser::{Lookup, Operator},
+        value::Value,
+    };
+
+    use super::*;
+
+    fn lex_lines(code: &str) -> Vec<Vec<Spanned<Token>>> {
+        split_lines(Lexer::lex_file(code).unwrap())
+    }
+
+    /// The items of an expression without their spans.
+    fn items(expression: &[Spanned<Postfix>]) -> Vec<Postfix> {
+        expression.iter().map(|item| item.node.clone()).collect()
+    }
+
+    #[test]
+    fn nested_if() {
+        let code = lex_lines(
+            "Si (a > 1) Entonces
+                Si (a > 2) Entonces
+                    Escribir 'dos'
+                FinSi
+                Escribir 'uno'
+            FinSi
+            Escribir 'fin'",
+        );
+        let ast = build_ast(code).unwrap();
+
+        assert_eq!(ast.len(), 2);
+        let ASTNode::IfStatement { code, .. } = &ast[0].node else {
+            panic!("expected an IfStatement, got {:?}", ast[0]);
+        };
+        assert_eq!(code.len(), 2);
+        assert!(matches!(&code[0].node, ASTNode::IfStatement { code, .. } if code.len() == 1));
+        assert!(matches!(code[1].node, ASTNode::WriteStatement { .. }));
+    }
+
+    #[test]
+    fn write_arguments() {
+        let code = lex_lines("Escribir \"Total: \", suma, Maximo(a, b), v[i, j]");
+        let ast = build_ast(code).unwrap();
+
+        let ASTNode::WriteStatement {
+            expressions,
+            newline,
+        } = &ast[0].node
+        else {
+            panic!("expected an Escribir, got {ast:?}");
+        };
+        assert_eq!(expressions.len(), 4);
+        assert_eq!(
+            items(&expressions[0]),
+            vec![Postfix::Operand(Value::Caracter("Total: ".to_string()))]
+        );
+        assert!(newline);
+    }
+
+    #[test]
+    fn write_without_newline() {
+        let code = lex_lines(
+            "Escribir Sin Saltar \"Ingrese un número: \"
+            Escribir a, b Sin Bajar
+            Escribir a, Sin Saltar, b",
+        );
+        let ast = build_ast(code).unwrap();
+
+        for statement in &ast {
+            let ASTNode::WriteStatement {
+                expressions,
+                newline,
+            } = &statement.node
+            else {
+                panic!("expected an Escribir, got {statement:?}");
+            };
+            assert!(!newline);
+            assert!(expressions.iter().all(|expression| !expression.is_empty()));
+        }
+        assert!(
+            matches!(&ast[2].node, ASTNode::WriteStatement { expressions, .. } if expressions.len() == 2)
+        );
+    }
+
+    #[test]
+    fn write_modifier_inside_expression() {
+        let code = lex_lines(
+            "Algoritmo t
+                Escribir 1 + Sin Bajar 2
+                Escribir a, Sin Saltar b, c
+                Escribir Sin Saltar a Sin Bajar
+            FinAlgoritmo",
+        );
+        let errors = build_program(code).unwrap_err();
+
+        let lines: Vec<usize> = errors.iter().map(|error| error.span.line).collect();
+        assert_eq!(lines, vec![2, 3]);
+        assert!(errors
+            .iter()
+            .all(|error| matches!(error.node.error, PossibleErrors::SyntaxError)));
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        let code = lex_lines(
+            "Algoritmo t
+                Escribir 'antes'
+                z <- (1 + 2
+                Escribir 1 2
+                x <- 3 +
+                Si a > Entonces
+                FinSi
+                Leer v[1 +]
+            FinAlgoritmo",
+        );
+        let errors = build_program(code).unwrap_err();
+
+        let errors: Vec<(String, usize, usize)> = errors
+            .iter()
+            .map(|error| (error.node.to_string(), error.span.line, error.span.column))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("ERROR: Falta cerrar paréntesis.".to_string(), 3, 22),
+                ("ERROR -1: Error de sintaxis.".to_string(), 4, 28),
+                (
+                    "ERROR 224: Falta operando (despues de +).".to_string(),
+                    5,
+                    24
+                ),
+                (
+                    "ERROR 224: Falta operando (despues de >).".to_string(),
+                    6,
+                    22
+                ),
+                (
+                    "ERROR 224: Falta operando (despues de +).".to_string(),
+                    8,
+                    26
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_needs_expressions() {
+        let code = lex_lines(
+            "Algoritmo t
+                Escribir a,
+                a <-
+                Escribir
+                Escribir Sin Saltar
+                Escribir , a
+            FinAlgoritmo",
+        );
+        let errors = build_program(code).unwrap_err();
+
+        let errors: Vec<(String, usize)> = errors
+            .iter()
+            .map(|error| (error.node.to_string(), error.span.line))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("ERROR: Falta una expresión.".to_string(), 2),
+                ("ERROR 89: Asignación incompleta.".to_string(), 3),
+                ("ERROR: Falta una expresión.".to_string(), 4),
+                ("ERROR: Falta una expresión.".to_string(), 5),
+                ("ERROR: Falta una expresión.".to_string(), 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_block_end() {
+        let code = lex_lines("Escribir 'hola'\nFinSi");
+        let err = build_ast(code).unwrap_err();
+
+        assert!(matches!(err.error, PossibleErrors::UnexpectedBlockEnd(_)));
+    }
+
+    #[test]
+    fn error_lines() {
+        let code = lex_lines(
+            "Algoritmo t
+                Si a > 1 Entonces
+                    a <-
+                FinSi
+            FinAlgoritmo",
+        );
+        let errors = build_program(code).unwrap_err();
+        assert!(matches!(
+            errors[0].node.error,
+            PossibleErrors::IncompleteAssignment
+        ));
+        assert_eq!((errors[0].span.line, errors[0].span.column), (3, 21));
+
+        let code = lex_lines(
+            "Algoritmo t
+                Mientras a > 1 Hacer
+                    a <- a - 1
+            FinAlgoritmo",
+        );
+        // the block left open is reported, not the FinAlgoritmo that gave it away
+        let errors = build_program(code).unwrap_err();
+        assert!(matches!(
+            errors[0].node.error,
+            PossibleErrors::MissingBlockEnd(_)
+        ));
+        assert_eq!(errors[0].span.line, 2);
+    }
+
+    #[test]
+    fn every_syntax_error() {
+        let code = lex_lines(
+            "SubProceso Saludar(
+                Escribir \"hola\"
+            FinSubProceso
+            Algoritmo t
+                Si a > 1
+                    a <-
+                    Para i <- 1 Hacer
+                        Escribir i
+                    FinPara
+                FinSi
+                Mientras a > 1 Hacer
+                    a <- a - 1
+                FinSi
+                b <- 2; c <-
+            FinAlgoritmo",
+        );
+        let errors = build_program(code).unwrap_err();
+
+        let lines: Vec<usize> = errors.iter().map(|error| error.span.line).collect();
+        assert_eq!(lines, vec![1, 5, 6, 7, 11, 13, 14]);
+        assert!(matches!(
+            errors[4].node.error,
+            PossibleErrors::MissingBlockEnd(_)
+        ));
+        assert!(matches!(
+            errors[5].node.error,
+            PossibleErrors::UnexpectedBlockEnd(_)
+        ));
+    }
+
+    #[test]
+    fn specific_error_codes() {
+        let code = lex_lines(
+            "Algoritmo t
+                Si Entonces
+                FinSi
+                Para i <- 1 Con Paso 2 Hacer
+                FinPara
+                Saludar(1, 2
+            FinAlgoritmo
+            Algoritmo otro
+            FinAlgoritmo
+            Escribir 1",
+        );
+        let errors = build_program(code).unwrap_err();
+        let errors: Vec<String> = errors.iter().map(|e| e.node.to_string()).collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "ERROR: Falta una expresión.",
+                "ERROR: Falta Hasta.",
+                "ERROR: Falta cerrar paréntesis.",
+                "ERROR: Solo puede haber un Algoritmo.",
+                "ERROR: Instrucción fuera del Algoritmo.",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_block_end() {
+        let code = lex_lines("Si (VERDADERO) Entonces\nSi (FALSO) Entonces\nFinSi");
+        let err = build_ast(code).unwrap_err();
+
+        assert!(matches!(err.error, PossibleErrors::MissingBlockEnd(ref k) if k == "Si"));
     }
 
-    Ok(ast)
+    #[test]
+    fn repeat_terminators() {
+        let code = lex_lines(
+            "Repetir
+                Repetir
+                    a <- a + 1
+                Mientras Que a < 3
+            Hasta Que a > 10",
+        );
+        let ast = build_ast(code).unwrap();
+
+        assert_eq!(ast.len(), 1);
+        let ASTNode::RepeatStatement { code, until, .. } = &ast[0].node else {
+            panic!("expected a RepeatStatement, got {:?}", ast[0]);
+        };
+        assert!(until);
+        assert!(matches!(
+            &code[0].node,
+            ASTNode::RepeatStatement { until: false, condition, .. } if condition.len() == 3
+        ));
+    }
+
+    #[test]
+    fn for_with_step() {
+        let code = lex_lines("Para i <- 10 Hasta 1 Con Paso -2 Hacer\nEscribir i\nFinPara");
+        let ast = build_ast(code).unwrap();
+
+        let ASTNode::ForStatement {
+            variable,
+            start,
+            end,
+            step,
+            code,
+        } = &ast[0].node
+        else {
+            panic!("expected a ForStatement, got {:?}", ast[0]);
+        };
+        assert_eq!(variable, "i");
+        assert_eq!(items(start), vec![Postfix::Operand(Value::Entero(10))]);
+        assert_eq!(items(end), vec![Postfix::Operand(Value::Entero(1))]);
+        assert_eq!(
+            step.as_deref().map(items),
+            Some(vec![
+                Postfix::Operand(Value::Entero(0)),
+                Postfix::Operand(Value::Entero(2)),
+                Postfix::Operator(Operator::Resta),
+            ])
+        );
+        assert_eq!(code.len(), 1);
+    }
+
+    #[test]
+    fn switch_cases() {
+        let code = lex_lines(
+            "Segun opcion Hacer
+                1: Escribir 'uno'
+                2, 3:
+                    Escribir 'dos'
+                    Escribir 'o tres'
+                De Otro Modo:
+                    Escribir 'otro'
+            FinSegun",
+        );
+        let ast = build_ast(code).unwrap();
+
+        let ASTNode::SwitchStatement {
+            selector,
+            cases,
+            default,
+        } = &ast[0].node
+        else {
+            panic!("expected a SwitchStatement, got {:?}", ast[0]);
+        };
+        assert_eq!(
+            items(selector),
+            vec![Postfix::Lookup(Lookup::Variable("opcion".to_string()))]
+        );
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].code.len(), 1);
+        assert_eq!(cases[1].labels.len(), 2);
+        assert_eq!(cases[1].code.len(), 2);
+        assert_eq!(default.len(), 1);
+    }
+
+    #[test]
+    fn array_targets() {
+        let code = lex_lines("Dimension v[10], m[3, 4]\nm[i + 1, v[2]] <- 5\nLeer v[1], a");
+        let ast = build_ast(code).unwrap();
+
+        let ASTNode::DimensionStatement { arrays } = &ast[0].node else {
+            panic!("expected a DimensionStatement, got {:?}", ast[0]);
+        };
+        assert_eq!(arrays.len(), 2);
+        assert_eq!(arrays[1].indices.len(), 2);
+
+        let ASTNode::Assignment { target, expression } = &ast[1].node else {
+            panic!("expected an Assignment, got {:?}", ast[1]);
+        };
+        assert_eq!(target.name, "m");
+        assert!(matches!(
+            items(&target.indices[1])[..],
+            [Postfix::Lookup(Lookup::Element(ref name, _))] if name == "v"
+        ));
+        assert_eq!(items(expression), vec![Postfix::Operand(Value::Entero(5))]);
+
+        let ASTNode::ReadStatement { variables } = &ast[2].node else {
+            panic!("expected a ReadStatement, got {:?}", ast[2]);
+        };
+        assert_eq!(variables[0].indices.len(), 1);
+        assert!(variables[1].indices.is_empty());
+    }
+
+    #[test]
+    fn program_with_functions() {
+        let code = lex_lines(
+            "Funcion r <- Doble(n)
+                r <- n * 2
+            FinFuncion
+            Algoritmo sin_titulo
+                Saludar
+                Escribir Doble(2)
+            FinAlgoritmo
+            SubProceso Saludar()
+                Escribir 'hola'
+            FinSubProceso",
+        );
+        let program = build_program(code).unwrap();
+
+        assert_eq!(program.functions.len(), 2);
+        assert_eq!(program.functions[0].name, "Doble");
+        assert_eq!(program.functions[0].parameters[0].name, "n");
+        assert_eq!(program.functions[0].return_variable, Some("r".to_string()));
+        assert!(program.functions[1].parameters.is_empty());
+        assert!(matches!(
+            &program.algorithm[0].node,
+            ASTNode::CallStatement { name, arguments } if name == "Saludar" && arguments.is_empty()
+        ));
+    }
+
+    #[test]
+    fn duplicate_functions() {
+        let code = lex_lines(
+            "SubProceso A
+                Escribir 'uno'
+            FinSubProceso
+            SubProceso a
+                Escribir 'dos'
+            FinSubProceso
+            Funcion r <- A(n)
+            FinFuncion
+            Algoritmo t
+                A
+            FinAlgoritmo",
+        );
+        let errors = build_program(code).unwrap_err();
+
+        let errors: Vec<(String, usize)> = errors
+            .iter()
+            .map(|error| (error.node.to_string(), error.span.line))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("ERROR: El subproceso a ya fue definido.".to_string(), 4),
+                ("ERROR: El subproceso A ya fue definido.".to_string(), 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_declarations() {
+        let code = lex_lines(
+            "Funcion r <- Doble(n)
+                Definir n, r Como Entero
+                r <- n * 2
+            FinFuncion
+            Algoritmo t
+                Definir n Como Entero
+                Mientras n < 3 Hacer
+                    Definir total Como Real
+                    n <- n + 1
+                FinMientras
+                Si n > 0 Entonces
+                    Definir N Como Real
+                FinSi
+                Definir a, b, A Como Logico
+                Definir total Como Real
+            FinAlgoritmo",
+        );
+        let errors = build_program(code).unwrap_err();
+
+        // a subproceso can use the names of the algorithm, a loop declares only once
+        let errors: Vec<(String, usize)> = errors
+            .iter()
+            .map(|error| (error.node.to_string(), error.span.line))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("ERROR: La variable N ya fue definida.".to_string(), 12),
+                ("ERROR: La variable A ya fue definida.".to_string(), 14),
+                ("ERROR: La variable total ya fue definida.".to_string(), 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn parameter_passing() {
+        let code = lex_lines(
+            "SubProceso Intercambiar(a Por Referencia, b Por Referencia, c, d Por Valor)
+            FinSubProceso
+            Algoritmo t
+            FinAlgoritmo",
+        );
+        let program = build_program(code).unwrap();
+
+        let pass_by: Vec<PassBy> = program.functions[0]
+            .parameters
+            .iter()
+            .map(|parameter| parameter.pass_by)
+            .collect();
+        assert_eq!(
+            pass_by,
+            vec![
+                PassBy::Reference,
+                PassBy::Reference,
+                PassBy::Default,
+                PassBy::Value
+            ]
+        );
+    }
+
+    #[test]
+    fn else_if_chain() {
+        let code = lex_lines(
+            "Si (a = 1) Entonces
+                Escribir 'uno'
+            Sino Si (a = 2) Entonces
+                Escribir 'dos'
+            Sino
+                Escribir 'otro'
+            FinSi
+            FinSi",
+        );
+        let ast = build_ast(code).unwrap();
+
+        assert_eq!(ast.len(), 1);
+        let ASTNode::IfStatement { else_code, .. } = &ast[0].node else {
+            panic!("expected an IfStatement, got {:?}", ast[0]);
+        };
+        assert_eq!(else_code.len(), 1);
+        assert!(matches!(
+            &else_code[0].node,
+            ASTNode::IfStatement { code, else_code, .. } if code.len() == 1 && else_code.len() == 1
+        ));
+    }
 }
@@ -1,19 +1,65 @@
 use std::fmt;
 
+use crate::span::Span;
+use crate::tokens::Type;
+
 #[derive(Debug, Clone)]
-#[allow(dead_code)]
 pub enum PossibleErrors {
-    MissingArguments,
-    MissingTypeOrUnvalidType,
-    WrongType,
+    // structure of the program
+    MissingAlgorithm,
+    DuplicateAlgorithm,
+    OutsideAlgorithm,
+    UnexpectedBlockEnd(String),
+    MissingBlockEnd(String),
+
+    // syntax
     SyntaxError,
     InvalidInstruction,
     IncompleteAssignment,
-    VariableNotFound(String),
-
-    // Should be used, but haven't got a solution to the postfix_stack_evaluator problem.
+    MissingKeyword(String),
+    MissingExpression,
+    MissingClosingParenthesis,
+    UnexpectedClosingParenthesis,
+    UnterminatedString,
+    InvalidNumber(String),
+    InvalidCharacter(char),
     MissingOperandBefore(String),
     MissingOperandAfter(String),
+
+    // types
+    MissingTypeOrUnvalidType,
+    TypeMismatch,
+    /// A value of the wrong type stored in a variable: its name, its declared type and
+    /// the name of the value's type.
+    WrongType(String, Type, String),
+    NotNumeric,
+    ConditionNotLogical,
+
+    // variables and arrays
+    VariableNotFound(String),
+    NotAnArray(String),
+    IndexOutOfBounds(String),
+    WrongIndexCount(String),
+    ArrayAlreadyDimensioned(String),
+    InvalidDimension(String),
+
+    // subprocesos
+    MissingArguments,
+    TooManyArguments,
+    FunctionNotFound(String),
+    NoReturnValue(String),
+    ReferenceToExpression(String),
+
+    // running
+    DivisionByZero,
+    IntegerOverflow,
+
+    // only this interpreter reports these
+    RecursionLimit(usize),
+    MissingInput,
+    ZeroStep,
+    DuplicateDeclaration(String),
+    DuplicateFunction(String),
 }
 
 #[derive(Debug, Clone)]
@@ -21,11 +67,51 @@ pub struct Code {
     pub error: PossibleErrors,
 }
 
+// errors are written with the number PSeInt gives them, those whose number isn't known
+// are written with their message alone
 impl fmt::Display for Code {
+    #[allow(clippy::too_many_lines)]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.error {
             PossibleErrors::MissingArguments => write!(f, "ERROR 53: Faltan parámetros."),
-            PossibleErrors::WrongType => write!(f, "ERROR 125: No coinciden los tipos."),
+            PossibleErrors::TypeMismatch => write!(f, "ERROR 125: No coinciden los tipos."),
+            PossibleErrors::WrongType(name, expected, found) => write!(
+                f,
+                "ERROR 125: No coinciden los tipos, {name} es de tipo {expected} y el valor es de tipo {found}."
+            ),
+            PossibleErrors::DuplicateAlgorithm => {
+                write!(f, "ERROR: Solo puede haber un Algoritmo.")
+            }
+            PossibleErrors::OutsideAlgorithm => {
+                write!(f, "ERROR: Instrucción fuera del Algoritmo.")
+            }
+            PossibleErrors::MissingKeyword(keyword) => write!(f, "ERROR: Falta {keyword}."),
+            PossibleErrors::MissingExpression => write!(f, "ERROR: Falta una expresión."),
+            PossibleErrors::MissingClosingParenthesis => {
+                write!(f, "ERROR: Falta cerrar paréntesis.")
+            }
+            PossibleErrors::UnexpectedClosingParenthesis => {
+                write!(f, "ERROR: Paréntesis de cierre sin abrir.")
+            }
+            PossibleErrors::UnterminatedString => {
+                write!(f, "ERROR: Falta cerrar la cadena de texto.")
+            }
+            PossibleErrors::InvalidNumber(text) => {
+                write!(f, "ERROR: Constante numérica no válida ({text}).")
+            }
+            PossibleErrors::InvalidCharacter(ch) => {
+                write!(f, "ERROR: Caracter no válido ({ch}).")
+            }
+            PossibleErrors::NotNumeric => {
+                write!(f, "ERROR: Se esperaba una expresión numérica.")
+            }
+            PossibleErrors::ConditionNotLogical => {
+                write!(f, "ERROR: La condición no es de tipo lógico.")
+            }
+            PossibleErrors::DivisionByZero => write!(f, "ERROR: División por cero."),
+            PossibleErrors::IntegerOverflow => {
+                write!(f, "ERROR: El resultado no entra en un Entero.")
+            }
             PossibleErrors::MissingTypeOrUnvalidType => {
                 write!(f, "ERROR 46: Falta tipo de dato o tipo no válido.")
             }
@@ -35,6 +121,58 @@ impl fmt::Display for Code {
             PossibleErrors::VariableNotFound(var_name) => {
                 write!(f, "ERROR 215: Variable no inicializada ({var_name})")
             }
+            PossibleErrors::UnexpectedBlockEnd(keyword) => {
+                write!(f, "ERROR: {keyword} mal colocado.")
+            }
+            PossibleErrors::MissingBlockEnd(keyword) => {
+                write!(f, "ERROR: Falta cerrar {keyword}.")
+            }
+            PossibleErrors::NotAnArray(name) => {
+                write!(f, "ERROR: {name} no es un arreglo.")
+            }
+            PossibleErrors::IndexOutOfBounds(name) => {
+                write!(f, "ERROR: Subíndice fuera de rango ({name}).")
+            }
+            PossibleErrors::WrongIndexCount(name) => {
+                write!(f, "ERROR: Cantidad de subíndices incorrecta ({name}).")
+            }
+            PossibleErrors::ArrayAlreadyDimensioned(name) => {
+                write!(f, "ERROR: El arreglo {name} ya fue dimensionado.")
+            }
+            PossibleErrors::InvalidDimension(name) => {
+                write!(f, "ERROR: Dimensión no válida ({name}).")
+            }
+            PossibleErrors::MissingAlgorithm => {
+                write!(f, "ERROR: Falta la sección Algoritmo.")
+            }
+            PossibleErrors::FunctionNotFound(name) => {
+                write!(f, "ERROR: El subproceso {name} no existe.")
+            }
+            PossibleErrors::TooManyArguments => write!(f, "ERROR: Demasiados parámetros."),
+            PossibleErrors::NoReturnValue(name) => {
+                write!(
+                    f,
+                    "ERROR: El subproceso {name} no devuelve ningún valor."
+                )
+            }
+            PossibleErrors::ReferenceToExpression(parameter) => write!(
+                f,
+                "ERROR: El parámetro {parameter} se pasa por referencia, debe recibir una variable."
+            ),
+            PossibleErrors::RecursionLimit(depth) => write!(
+                f,
+                "ERROR: Se superó la profundidad máxima de llamadas ({depth})."
+            ),
+            PossibleErrors::MissingInput => {
+                write!(f, "ERROR: No hay más datos para leer.")
+            }
+            PossibleErrors::DuplicateDeclaration(name) => {
+                write!(f, "ERROR: La variable {name} ya fue definida.")
+            }
+            PossibleErrors::DuplicateFunction(name) => {
+                write!(f, "ERROR: El subproceso {name} ya fue definido.")
+            }
+            PossibleErrors::ZeroStep => write!(f, "ERROR: El paso de un Para no puede ser cero."),
             PossibleErrors::MissingOperandBefore(operand) => {
                 write!(f, "ERROR 234: Falta operando (antes de {operand}).")
             }
@@ -44,3 +182,103 @@ impl fmt::Display for Code {
         }
     }
 }
+
+/// An error raised while running the algorithm, along with the token or statement that
+/// caused it and the subprocesos that were being run when it happened, outermost first.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub code: Code,
+    pub span: Span,
+    pub call_stack: Vec<String>,
+}
+
+impl RuntimeError {
+    /// Renders the error like `report` does, followed by the call stack.
+    pub fn report(&self, source: &str) -> String {
+        let text = report(source, &self.code, self.span);
+
+        if self.call_stack.is_empty() {
+            return text;
+        }
+
+        // deep recursion would print the same name thousands of times, so repeats are grouped
+        let mut lines = Vec::new();
+        let mut calls = self.call_stack.iter().rev().peekable();
+        while let Some(name) = calls.next() {
+            let mut times = 1;
+            while calls.next_if_eq(&name).is_some() {
+                times += 1;
+            }
+
+            if times == 1 {
+                lines.push(format!("    en {name}"));
+            } else {
+                lines.push(format!("    en {name} ({times} veces)"));
+            }
+        }
+
+        format!("{text}\nPila de llamadas:\n{}", lines.join("\n"))
+    }
+}
+
+/// Renders an error the way the CLI shows it: the message, then the line `span` points
+/// at with carets under the part it covers.
+pub fn report(source: &str, code: &Code, span: Span) -> String {
+    let Some(line) = span
+        .line
+        .checked_sub(1)
+        .and_then(|index| source.lines().nth(index))
+    else {
+        return code.to_string();
+    };
+
+    let before: String = line.chars().take(span.column.saturating_sub(1)).collect();
+    let line_start = span.start.saturating_sub(before.len());
+    let end = span.end.min(line_start + line.len());
+    let width = source
+        .get(span.start..end)
+        .map_or(1, |covered| covered.chars().count().max(1));
+
+    // tabs are kept so the carets line up with the code above them
+    let indent: String = before
+        .chars()
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    let number = span.line.to_string();
+    let gutter = " ".repeat(number.len());
+
+    let carets = "^".repeat(width);
+
+    format!(
+        "{code}\n{gutter}--> línea {}, columna {}\n{gutter} |\n{number} | {line}\n{gutter} | {indent}{carets}",
+        span.line, span.column
+    )
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn report_points_at_span() {
+        let source = "Algoritmo t\n\tx <- año + 1\nFinAlgoritmo";
+        let code = Code {
+            error: PossibleErrors::VariableNotFound("año".to_string()),
+        };
+        let span = Span {
+            line: 2,
+            column: 7,
+            start: 18,
+            end: 22,
+        };
+
+        assert_eq!(
+            report(source, &code, span),
+            "ERROR 215: Variable no inicializada (año)
+ --> línea 2, columna 7
+  |
+2 | \tx <- año + 1
+  | \t     ^^^"
+        );
+    }
+}
@@ -1,125 +1,1074 @@
+use std::collections::HashMap;
 use std::io::{self, Write};
+use std::rc::Rc;
 
-use log::{debug, info, trace, warn};
+use clap::ValueEnum;
+use log::{debug, trace};
 
-use crate::ast::{build_ast, ASTNode};
-use crate::error::{Code, PossibleErrors};
-use crate::lexer::Lexer;
-use crate::memory::Memoria;
-use crate::parser::{postfix_stack_evaluator, shunting_yard};
-use crate::tokens::{convert_to_type, Keyword, Token, Type};
+use crate::ast::{ASTNode, Expression, Function, Parameter, PassBy, SwitchCase, Target};
+use crate::error::{Code, PossibleErrors, RuntimeError};
+use crate::memory::{Memoria, Slot};
+use crate::parser::{add_numbers, compare_values, postfix_stack_evaluator, Lookup, Postfix};
+use crate::span::{Span, Spanned};
+use crate::tokens::{identifier_key, Type};
+use crate::value::Value;
 
-pub struct Interpreter {
-    code: Vec<Vec<Token>>,
-    memory: Memoria,
+/// Converts a line typed by the user into a value of type `tipo`, if it fits.
+fn parse_input(line: &str, tipo: Type) -> Option<Value> {
+    let input = line.trim();
+
+    match tipo {
+        Type::Entero => input.parse().ok().map(Value::Entero),
+        // `nan` and `inf` parse as floats but aren't numbers, so they don't fit in a Real
+        Type::Real => input
+            .parse()
+            .ok()
+            .filter(|num: &f64| num.is_finite())
+            .map(Value::Real),
+        Type::Logico => match input.to_lowercase().as_str() {
+            "verdadero" => Some(Value::Logico(true)),
+            "falso" => Some(Value::Logico(false)),
+            _ => None,
+        },
+        // text keeps its spaces, only the line break is dropped
+        Type::Caracter => Some(Value::Caracter(
+            line.trim_end_matches(['\n', '\r']).to_string(),
+        )),
+        // without a type it's whatever the input looks like, text when it's nothing else
+        Type::None => [Type::Entero, Type::Real, Type::Logico, Type::Caracter]
+            .into_iter()
+            .find_map(|tipo| parse_input(line, tipo)),
+    }
+}
+
+/// How strictly the algorithm is run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Profile {
+    /// Variables start with their type's default value.
+    #[default]
+    Flexible,
+    /// Reading a variable before assigning it is an error.
+    Estricto,
 }
 
-fn check_same_type(t1: &Token, t2: &Token) -> bool {
-    std::mem::discriminant(t1) == std::mem::discriminant(t2)
+pub struct Interpreter {
+    memory: Memoria,
+    functions: HashMap<String, Rc<Function>>,
+    /// Names of the subprocesos being run, one for each frame above the algorithm's.
+    call_stack: Vec<String>,
+    max_depth: usize,
+    /// The statement being run, or the part of an expression being evaluated in it,
+    /// errors are reported there.
+    span: Span,
 }
 
 impl Interpreter {
-    pub fn new(lines_of_code: &[&str]) -> Interpreter {
-        let code = lines_of_code
-            .iter()
-            .map(|f| Lexer::lex(f))
-            .filter(|f| !f.is_empty())
+    pub fn new(functions: Vec<Function>, max_depth: usize, profile: Profile) -> Interpreter {
+        let memory = match profile {
+            Profile::Flexible => Memoria::new(),
+            Profile::Estricto => Memoria::strict(),
+        };
+        let functions = functions
+            .into_iter()
+            .map(|function| (identifier_key(&function.name), Rc::new(function)))
             .collect();
 
-        let memory = Memoria::new();
+        Self {
+            memory,
+            functions,
+            call_stack: Vec::new(),
+            max_depth,
+            span: Span::default(),
+        }
+    }
+
+    /// Runs the algorithm, any error comes with the token or statement that caused it and the
+    /// subprocesos that were running when it happened.
+    pub fn execute(&mut self, algorithm: &[Spanned<ASTNode>]) -> Result<(), RuntimeError> {
+        self.run(algorithm).map_err(|code| RuntimeError {
+            code,
+            span: self.span,
+            call_stack: std::mem::take(&mut self.call_stack),
+        })
+    }
+
+    /// Works out what a parameter is bound to, an alias to the caller's variable or array
+    /// element when passed by reference, or a copy of the argument otherwise.
+    fn bind_argument(
+        &mut self,
+        parameter: &Parameter,
+        argument: &[Spanned<Postfix>],
+    ) -> Result<Slot, Code> {
+        // only a variable or an element on its own can be aliased
+        let place = match argument {
+            [item] => match &item.node {
+                Postfix::Lookup(Lookup::Variable(name)) => Some((name, &[][..])),
+                Postfix::Lookup(Lookup::Element(name, indices)) => Some((name, indices.as_slice())),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let whole_array =
+            place.is_some_and(|(name, indices)| indices.is_empty() && self.memory.is_array(name));
+        let by_reference = match parameter.pass_by {
+            PassBy::Reference => true,
+            PassBy::Value => false,
+            PassBy::Default => whole_array,
+        };
+
+        match place {
+            Some((name, indices)) if by_reference => {
+                let indices = if indices.is_empty() {
+                    Vec::new()
+                } else {
+                    self.evaluate_indices(indices)?
+                };
+
+                self.memory.reference(name, &indices)
+            }
+            Some((name, _)) if whole_array => self.memory.copy(name).ok_or_else(|| Code {
+                error: PossibleErrors::VariableNotFound(name.clone()),
+            }),
+            _ if by_reference => Err(Code {
+                error: PossibleErrors::ReferenceToExpression(parameter.name.clone()),
+            }),
+            _ => Ok(Slot::from_value(self.evaluate_expression(argument)?)),
+        }
+    }
+
+    /// Runs a subproceso in its own frame, returning the value of its return variable if it has one.
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> Result<Option<Value>, Code> {
+        let function =
+            Rc::clone(
+                self.functions
+                    .get(&identifier_key(name))
+                    .ok_or_else(|| Code {
+                        error: PossibleErrors::FunctionNotFound(name.to_string()),
+                    })?,
+            );
+
+        if arguments.len() < function.parameters.len() {
+            return Err(Code {
+                error: PossibleErrors::MissingArguments,
+            });
+        } else if arguments.len() > function.parameters.len() {
+            return Err(Code {
+                error: PossibleErrors::TooManyArguments,
+            });
+        }
+
+        // arguments are evaluated in the caller's frame
+        let slots = function
+            .parameters
+            .iter()
+            .zip(arguments)
+            .map(|(parameter, argument)| self.bind_argument(parameter, argument))
+            .collect::<Result<Vec<Slot>, Code>>()?;
+
+        if self.call_stack.len() >= self.max_depth {
+            return Err(Code {
+                error: PossibleErrors::RecursionLimit(self.max_depth),
+            });
+        }
+
+        trace!("Call {name} with {slots:?}");
+        self.call_stack.push(function.name.clone());
+        self.memory.push_frame();
+        for (parameter, slot) in function.parameters.iter().zip(slots) {
+            self.memory.shadow(&parameter.name, slot);
+        }
+        // the return variable can be assigned without declaring it first
+        if let Some(variable) = &function.return_variable {
+            if self.memory.get_type(variable).is_none() {
+                self.memory.shadow(variable, Slot::untyped());
+            }
+        }
+
+        // on error the frame is left in place, so `execute` can report where it happened
+        self.run(&function.code)?;
+        let returned = function
+            .return_variable
+            .as_ref()
+            .map(|variable| {
+                self.memory.get(variable).cloned().ok_or_else(|| Code {
+                    error: PossibleErrors::VariableNotFound(variable.clone()),
+                })
+            })
+            .transpose()?;
+
+        self.memory.pop_frame();
+        self.call_stack.pop();
+
+        Ok(returned)
+    }
+
+    /// Evaluates an expression, moving `span` to each variable, element or call while it's
+    /// looked up and back once it's done. On error `span` is left where it happened.
+    fn evaluate_expression(&mut self, expression: &[Spanned<Postfix>]) -> Result<Value, Code> {
+        postfix_stack_evaluator(expression, |lookup, span| {
+            let outer = self.span;
+            self.span = span;
+
+            // an error inside a call already left `span` at the statement that failed
+            let value = self
+                .look_up(lookup)
+                .map_err(|code| Spanned::new(code, self.span))?;
+            self.span = outer;
+
+            Ok(value)
+        })
+        .map_err(|error| self.locate(error))
+    }
+
+    /// Moves `span` to an error found inside an expression, so it's reported there.
+    fn locate(&mut self, error: Spanned<Code>) -> Code {
+        self.span = error.span;
+        error.node
+    }
+
+    /// The value of a variable, array element or subproceso call inside an expression.
+    fn look_up(&mut self, lookup: &Lookup) -> Result<Value, Code> {
+        match lookup {
+            Lookup::Variable(name) => match self.memory.get(name) {
+                Some(value) => Ok(value.clone()),
+                None if self.memory.is_array(name) => Err(Code {
+                    error: PossibleErrors::WrongIndexCount(name.clone()),
+                }),
+                None => Err(Code {
+                    error: PossibleErrors::VariableNotFound(name.clone()),
+                }),
+            },
+            Lookup::Element(name, indices) => {
+                let indices = indices
+                    .iter()
+                    .map(|index| self.evaluate_index(index))
+                    .collect::<Result<Vec<i64>, Code>>()?;
+
+                self.memory.get_element(name, &indices).cloned()
+            }
+            Lookup::Call(name, arguments) => self.call(name, arguments)?.ok_or_else(|| Code {
+                error: PossibleErrors::NoReturnValue(name.clone()),
+            }),
+        }
+    }
 
-        Self { code, memory }
+    /// Evaluates an array index, which must be an integer.
+    fn evaluate_index(&mut self, index: &[Spanned<Postfix>]) -> Result<i64, Code> {
+        self.evaluate_expression(index)?.as_integer().ok_or(Code {
+            error: PossibleErrors::TypeMismatch,
+        })
     }
 
-    pub fn run(&mut self, ast: Vec<ASTNode>) {
+    /// Evaluates the indices of an array access written outside of an expression.
+    fn evaluate_indices(&mut self, indices: &[Expression]) -> Result<Vec<i64>, Code> {
+        indices
+            .iter()
+            .map(|index| self.evaluate_index(index))
+            .collect()
+    }
+
+    fn evaluate_number(&mut self, expression: &[Spanned<Postfix>]) -> Result<Value, Code> {
+        match self.evaluate_expression(expression)? {
+            number @ (Value::Entero(_) | Value::Real(_)) => Ok(number),
+            _ => Err(Code {
+                error: PossibleErrors::NotNumeric,
+            }),
+        }
+    }
+
+    /// Evaluates the condition of a Si, Mientras or Repetir, which must be a Logico.
+    fn evaluate_condition(&mut self, condition: &[Spanned<Postfix>]) -> Result<bool, Code> {
+        match self.evaluate_expression(condition)? {
+            Value::Logico(result) => Ok(result),
+            _ => Err(Code {
+                error: PossibleErrors::ConditionNotLogical,
+            }),
+        }
+    }
+
+    /// Assigns `value` to a variable or, when indices are given, to one of its elements.
+    fn assign(&mut self, target: &Target, value: Value) -> Result<(), Code> {
+        if target.indices.is_empty() {
+            return self.memory.set(target.name.clone(), value);
+        }
+
+        let indices = self.evaluate_indices(&target.indices)?;
+        self.memory.set_element(&target.name, &indices, value)
+    }
+
+    /// Writes every expression one after the other, ending the line unless told not to.
+    fn write(&mut self, expressions: &[Expression], newline: bool) -> Result<(), Code> {
+        let mut line = String::new();
+        for expression in expressions {
+            line += &self.evaluate_expression(expression)?.to_string();
+        }
+
+        if newline {
+            println!("{line}");
+        } else {
+            print!("{line}");
+            io::stdout().flush().ok();
+        }
+
+        Ok(())
+    }
+
+    /// Reads a line from stdin into a variable or array element, converted to its type.
+    fn read(&mut self, target: &Target) -> Result<(), Code> {
+        let tipo = self.memory.get_type(&target.name).ok_or_else(|| Code {
+            error: PossibleErrors::VariableNotFound(target.name.clone()),
+        })?;
+        // a wrong index is reported before waiting for the input
+        if !target.indices.is_empty() {
+            let indices = self.evaluate_indices(&target.indices)?;
+            self.memory.check_indices(&target.name, &indices)?;
+        }
+
+        // a prompt written right before has to show up before waiting
+        io::stdout().flush().ok();
+        let mut input = String::new();
+        match io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => {
+                return Err(Code {
+                    error: PossibleErrors::MissingInput,
+                })
+            }
+            Ok(_) => {}
+        }
+
+        let Some(value) = parse_input(&input, tipo) else {
+            // name what was typed in the error
+            let found = parse_input(&input, Type::None)
+                .map_or_else(|| Type::Caracter.to_string(), |value| value.type_name());
+
+            return Err(Code {
+                error: PossibleErrors::WrongType(target.name.clone(), tipo, found),
+            });
+        };
+
+        trace!("Read {value} into {}", target.name);
+        self.assign(target, value)
+    }
+
+    /// Runs a block of statements. On error `span` is left at the statement that failed.
+    fn run(&mut self, ast: &[Spanned<ASTNode>]) -> Result<(), Code> {
+        let outer = self.span;
+
         for statement in ast {
-            match statement {
-                ASTNode::VariableDeclaration { names, var_type } => {
-                    for name in names {
-                        trace!("Create {name}, set to {var_type}");
-                        self.memory.create(name, var_type);
-                    }
-                }
-                ASTNode::Assignment { name, expression } => {
-                    // expression is a vec of tokens, if the length is more than 1 then it's
-                    // probably a mathematical expression, otherwise it's just 1 token
-                    // like falso for example, which just set the variable to false in one token
-
-                    // it technically is more expensive to retrieve this value first
-                    // then check for shunting_yard, but I can refactor it later!
-                    let mut result: Token = expression.get(0).unwrap().clone();
-
-                    if let Ok(postfix) = shunting_yard(expression, &self.memory) {
-                        // result = postfix_stack_evaluator().unwrap();
-                        result = postfix_stack_evaluator(postfix).unwrap();
-                    } else {
-                        warn!("shunting_yard couldn't be completed")
-                    }
+            self.span = statement.span;
+            self.run_statement(&statement.node)?;
+        }
 
-                    trace!("Set {} to {}", name, result);
-                    self.memory.set(name, result).unwrap();
+        self.span = outer;
+        Ok(())
+    }
 
-                    // debug!("{:?}", self.memory);
+    fn run_statement(&mut self, statement: &ASTNode) -> Result<(), Code> {
+        match statement {
+            ASTNode::VariableDeclaration { names, var_type } => {
+                for name in names {
+                    trace!("Create {name}, set to {var_type}");
+                    self.memory.create(name, *var_type)?;
                 }
-                ASTNode::WriteStatement { expressions } => {
-                    let mut result: Token = expressions.get(0).unwrap().clone();
-
-                    if let Ok(postfix) = shunting_yard(expressions, &self.memory) {
-                        result = postfix_stack_evaluator(postfix).unwrap();
-                    } else {
-                        warn!("shunting_yard couldn't be completed")
-                    }
+            }
+            ASTNode::DimensionStatement { arrays } => {
+                for array in arrays {
+                    let dimensions = self
+                        .evaluate_indices(&array.indices)?
+                        .into_iter()
+                        .map(usize::try_from)
+                        .collect::<Result<Vec<usize>, _>>()
+                        .map_err(|_| Code {
+                            error: PossibleErrors::InvalidDimension(array.name.clone()),
+                        })?;
 
-                    println!("{}", result.get_as_string());
+                    trace!("Dimension {} to {dimensions:?}", array.name);
+                    self.memory.dimension(&array.name, dimensions)?;
                 }
-                ASTNode::IfStatement { condition, code } => {
-                    trace!("{:?}, {:?}", condition, code);
+            }
+            ASTNode::Assignment { target, expression } => {
+                let result = self.evaluate_expression(expression)?;
 
-                    let mut result: Token = Token::None;
+                trace!("Set {} to {result}", target.name);
+                self.assign(target, result)?;
+            }
+            ASTNode::WriteStatement {
+                expressions,
+                newline,
+            } => self.write(expressions, *newline)?,
+            ASTNode::IfStatement {
+                condition,
+                code,
+                else_code,
+            } => {
+                trace!("{condition:?}, {code:?}");
 
-                    if condition.len() == 1 {
-                        result = condition.get(0).unwrap().clone();
-                    }
+                let conditional = self.evaluate_condition(condition)?;
 
-                    if let Ok(postfix) = shunting_yard(condition, &self.memory) {
-                        if let Some(res) = postfix_stack_evaluator(postfix) {
-                            result = res;
-                        } else {
-                            warn!("postfix couldn't be completed")
-                        }
-                    } else {
-                        warn!("shunting_yard couldn't be completed")
-                    }
+                debug!("{conditional}");
 
-                    // token is variable
-                    // can only be logico type
-                    let conditional = match result {
-                        Token::Variable(variable) => {
-                            if let Some(value) = self.memory.get(variable) {
-                                match value.clone().get_as_string().as_str() {
-                                    "true" => true,
-                                    "false" => false,
-                                    _ => false,
-                                }
-                            } else {
-                                false // variable not found
-                            }
-                        }
-                        Token::Boolean(b) => b,
-                        _ => false,
-                    };
-
-                    debug!("{}", conditional);
-
-                    if conditional {
-                        let if_ast = build_ast(code).unwrap();
-                        self.run(if_ast);
+                if conditional {
+                    self.run(code)?;
+                } else {
+                    self.run(else_code)?;
+                }
+            }
+            ASTNode::WhileStatement { condition, code } => {
+                trace!("{condition:?}, {code:?}");
+
+                while self.evaluate_condition(condition)? {
+                    self.run(code)?;
+                }
+            }
+            ASTNode::RepeatStatement {
+                code,
+                condition,
+                until,
+            } => {
+                trace!("{code:?}, {condition:?}");
+
+                loop {
+                    self.run(code)?;
+
+                    if self.evaluate_condition(condition)? == *until {
+                        break;
                     }
                 }
-                _ => {
-                    warn!("unhandled statement")
+            }
+            ASTNode::ForStatement {
+                variable,
+                start,
+                end,
+                step,
+                code,
+            } => self.run_for(variable, start, end, step.as_deref(), code)?,
+            ASTNode::ForEachStatement {
+                variable,
+                array,
+                code,
+            } => self.run_for_each(variable, array, code)?,
+            ASTNode::SwitchStatement {
+                selector,
+                cases,
+                default,
+            } => self.run_switch(selector, cases, default)?,
+            ASTNode::CallStatement { name, arguments } => {
+                self.call(name, arguments)?;
+            }
+            ASTNode::ReadStatement { variables } => {
+                for variable in variables {
+                    self.read(variable)?;
                 }
             }
         }
+
+        Ok(())
+    }
+
+    /// Runs the first Segun branch with a label equal to the selector, or the default one.
+    fn run_switch(
+        &mut self,
+        selector: &[Spanned<Postfix>],
+        cases: &[SwitchCase],
+        default: &[Spanned<ASTNode>],
+    ) -> Result<(), Code> {
+        let selector = self.evaluate_expression(selector)?;
+
+        if !matches!(
+            selector,
+            Value::Entero(_) | Value::Real(_) | Value::Caracter(_)
+        ) {
+            return Err(Code {
+                error: PossibleErrors::TypeMismatch,
+            });
+        }
+
+        for case in cases {
+            for label in &case.labels {
+                let label = self.evaluate_expression(label)?;
+
+                let Some(equal) = compare_values(selector.clone(), label) else {
+                    return Err(Code {
+                        error: PossibleErrors::TypeMismatch,
+                    });
+                };
+
+                if equal {
+                    return self.run(&case.code);
+                }
+            }
+        }
+
+        self.run(default)
+    }
+
+    /// Runs a Para Cada loop over every element of `array` in row-major order, `variable`
+    /// aliases the current element so assigning to it changes the array.
+    fn run_for_each(
+        &mut self,
+        variable: &str,
+        array: &str,
+        code: &[Spanned<ASTNode>],
+    ) -> Result<(), Code> {
+        for n in 0..self.memory.element_count(array)? {
+            let element = self.memory.nth_reference(array, n)?;
+            let previous = self.memory.shadow(variable, element);
+
+            let result = self.run(code);
+            self.memory.restore(variable, previous);
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs a Para loop, the bounds and the step are evaluated once before the first iteration.
+    fn run_for(
+        &mut self,
+        variable: &str,
+        start: &[Spanned<Postfix>],
+        end: &[Spanned<Postfix>],
+        step: Option<&[Spanned<Postfix>]>,
+        code: &[Spanned<ASTNode>],
+    ) -> Result<(), Code> {
+        let start = self.evaluate_number(start)?;
+        let end = self.evaluate_number(end)?;
+        let last = end.as_real().unwrap_or_default();
+
+        // without Con Paso, PSeInt counts down when the start is past the end
+        let step = match step {
+            Some(step) => self.evaluate_number(step)?,
+            None if start.as_real() > end.as_real() => Value::Entero(-1),
+            None => Value::Entero(1),
+        };
+        let direction = step.as_real().unwrap_or_default();
+        // the variable would never get past the end
+        if direction == 0.0 {
+            return Err(Code {
+                error: PossibleErrors::ZeroStep,
+            });
+        }
+
+        trace!("Para {variable} from {start:?} to {end:?} by {step:?}");
+        self.memory.set(variable.to_string(), start)?;
+
+        loop {
+            let current = self.memory.get(variable).and_then(Value::as_real);
+            let Some(current) = current else {
+                return Err(Code {
+                    error: PossibleErrors::NotNumeric,
+                });
+            };
+
+            if (direction > 0.0 && current > last) || (direction < 0.0 && current < last) {
+                break;
+            }
+
+            self.run(code)?;
+
+            // the body may have changed the variable, so step from its current value
+            let current = self.memory.get(variable).cloned().ok_or_else(|| Code {
+                error: PossibleErrors::VariableNotFound(variable.to_string()),
+            })?;
+            self.memory
+                .set(variable.to_string(), add_numbers(current, step.clone())?)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use crate::ast::{build_program, Block};
+    use crate::lexer::{split_lines, Lexer};
+
+    use super::*;
+
+    fn interpreter_for(code: &str, max_depth: usize, profile: Profile) -> (Interpreter, Block) {
+        let program = build_program(split_lines(Lexer::lex_file(code).unwrap())).unwrap();
+
+        (
+            Interpreter::new(program.functions, max_depth, profile),
+            program.algorithm,
+        )
+    }
+
+    #[test]
+    fn recursive_factorial() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "Funcion r <- Factorial(n)
+                Definir r Como Entero
+                Si n <= 1 Entonces
+                    r <- 1
+                Sino
+                    r <- n * Factorial(n - 1)
+                FinSi
+            FinFuncion
+            Algoritmo t
+                Definir x Como Entero
+                x <- Factorial(5)
+            FinAlgoritmo",
+            100,
+            Profile::Flexible,
+        );
+
+        interpreter.execute(&algorithm).unwrap();
+        assert_eq!(interpreter.memory.get("x"), Some(&Value::Entero(120)));
+    }
+
+    #[test]
+    fn while_loop() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "Algoritmo t
+                Definir n, vueltas Como Entero
+                n <- 10
+                Mientras n > 1 Hacer
+                    n <- n - 3
+                    vueltas <- vueltas + 1
+                FinMientras
+                Mientras n Hacer
+                FinMientras
+            FinAlgoritmo",
+            10,
+            Profile::Flexible,
+        );
+
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        assert_eq!(interpreter.memory.get("vueltas"), Some(&Value::Entero(3)));
+        assert_eq!(interpreter.memory.get("n"), Some(&Value::Entero(1)));
+        assert_eq!(error.span.line, 8);
+        assert!(matches!(
+            error.code.error,
+            PossibleErrors::ConditionNotLogical
+        ));
+    }
+
+    #[test]
+    fn for_steps() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "Algoritmo t
+                Definir abajo, negativo, veces Como Entero
+                Definir x, suma Como Real
+                Para abajo <- 5 Hasta 1 Hacer
+                    veces <- veces + 1
+                FinPara
+                Para negativo <- 10 Hasta 1 Con Paso -3 Hacer
+                    veces <- veces + 1
+                FinPara
+                Para x <- 0 Hasta 1 Con Paso 0.25 Hacer
+                    suma <- suma + x
+                FinPara
+                Para abajo <- 1 Hasta 3 Con Paso 0 Hacer
+                FinPara
+            FinAlgoritmo",
+            10,
+            Profile::Flexible,
+        );
+
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        // 5, 4, 3, 2, 1 and then 10, 7, 4, 1
+        assert_eq!(interpreter.memory.get("veces"), Some(&Value::Entero(9)));
+        assert_eq!(interpreter.memory.get("abajo"), Some(&Value::Entero(0)));
+        assert_eq!(interpreter.memory.get("negativo"), Some(&Value::Entero(-2)));
+        assert_eq!(interpreter.memory.get("x"), Some(&Value::Real(1.25)));
+        assert_eq!(interpreter.memory.get("suma"), Some(&Value::Real(2.5)));
+        assert_eq!(error.span.line, 13);
+        assert!(matches!(error.code.error, PossibleErrors::ZeroStep));
+    }
+
+    #[test]
+    fn for_each_writes_back() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "Algoritmo t
+                Definir m Como Entero
+                Definir i, j, orden Como Entero
+                Dimension m[2, 3]
+                Para i <- 1 Hasta 2 Hacer
+                    Para j <- 1 Hasta 3 Hacer
+                        m[i, j] <- (i - 1) * 3 + j
+                    FinPara
+                FinPara
+                Para Cada x De m Hacer
+                    orden <- orden + 1
+                    x <- x * 10 + orden
+                FinPara
+            FinAlgoritmo",
+            10,
+            Profile::Flexible,
+        );
+
+        interpreter.execute(&algorithm).unwrap();
+        // the last digit is the order each element was visited in
+        let elements: Vec<Value> = (0..6)
+            .map(|n| {
+                interpreter
+                    .memory
+                    .get_element("m", &[n / 3 + 1, n % 3 + 1])
+                    .unwrap()
+                    .clone()
+            })
+            .collect();
+        assert_eq!(
+            elements,
+            [11, 22, 33, 44, 55, 66].map(Value::Entero).to_vec()
+        );
+        assert!(interpreter.memory.get("x").is_none());
+    }
+
+    #[test]
+    fn array_limits() {
+        let errors: Vec<String> = [
+            "Dimension m[100000, 100000, 100000, 100000]",
+            "Dimension m[5000, 5000]",
+            "Dimension m[0]",
+            "Dimension m[3]\nEscribir m[-9223372036854775807 - 1]",
+        ]
+        .into_iter()
+        .map(|code| {
+            let (mut interpreter, algorithm) = interpreter_for(
+                &format!("Algoritmo t\nDefinir m Como Entero\n{code}\nFinAlgoritmo"),
+                10,
+                Profile::Flexible,
+            );
+
+            interpreter
+                .execute(&algorithm)
+                .unwrap_err()
+                .code
+                .to_string()
+        })
+        .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "ERROR: Dimensión no válida (m).",
+                "ERROR: Dimensión no válida (m).",
+                "ERROR: Dimensión no válida (m).",
+                "ERROR: Subíndice fuera de rango (m).",
+            ]
+        );
+    }
+
+    #[test]
+    fn return_variable_without_definir() {
+        let functions = "Funcion r <- Doble(n)
+                r <- n * 2
+            FinFuncion
+            Funcion r <- Mitad(n)
+                Definir r Como Entero
+                r <- n / 2
+            FinFuncion
+            Funcion r <- Nada(n)
+            FinFuncion";
+
+        let (mut interpreter, algorithm) = interpreter_for(
+            &format!(
+                "{functions}
+                Algoritmo t
+                    Definir x Como Real
+                    x <- Doble(2) + Doble(0.25) + Mitad(8)
+                FinAlgoritmo"
+            ),
+            10,
+            Profile::Flexible,
+        );
+        interpreter.execute(&algorithm).unwrap();
+        assert_eq!(interpreter.memory.get("x"), Some(&Value::Real(8.5)));
+
+        let (mut interpreter, algorithm) = interpreter_for(
+            &format!("{functions}\nAlgoritmo t\nEscribir Nada(1)\nFinAlgoritmo"),
+            10,
+            Profile::Flexible,
+        );
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        assert!(matches!(
+            error.code.error,
+            PossibleErrors::VariableNotFound(ref name) if name == "r"
+        ));
+    }
+
+    #[test]
+    fn dimension_before_definir() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "Algoritmo t
+                Dimension v[3], w[2]
+                Definir v Como Entero
+                v[1] <- 4
+                w[1] <- 'a'
+                w[2] <- 1
+            FinAlgoritmo",
+            10,
+            Profile::Flexible,
+        );
+
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        assert_eq!(
+            interpreter.memory.get_element("v", &[1]).ok(),
+            Some(&Value::Entero(4))
+        );
+        assert_eq!(
+            interpreter.memory.get_element("v", &[3]).ok(),
+            Some(&Value::Entero(0))
+        );
+        assert_eq!(error.span.line, 6);
+        assert_eq!(
+            error.code.to_string(),
+            "ERROR 125: No coinciden los tipos, w es de tipo Caracter y el valor es de tipo Entero."
+        );
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "SubProceso Infinito(n)
+                Infinito(n + 1)
+            FinSubProceso
+            Algoritmo t
+                Infinito(1)
+            FinAlgoritmo",
+            50,
+            Profile::Flexible,
+        );
+
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        assert!(matches!(
+            error.code.error,
+            PossibleErrors::RecursionLimit(50)
+        ));
+        assert_eq!(
+            error.code.to_string(),
+            "ERROR: Se superó la profundidad máxima de llamadas (50)."
+        );
+        assert_eq!(error.call_stack, vec!["Infinito".to_string(); 50]);
+        assert_eq!(error.span.line, 2);
+    }
+
+    #[test]
+    fn declared_types() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "Algoritmo t
+                Definir e Como Entero
+                Definir r Como Real
+                r <- 3
+                e <- 8 / 2
+                e <- 2.5
+            FinAlgoritmo",
+            10,
+            Profile::Flexible,
+        );
+
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        assert_eq!(interpreter.memory.get("r"), Some(&Value::Real(3.0)));
+        assert_eq!(interpreter.memory.get("e"), Some(&Value::Entero(4)));
+        assert_eq!(error.span.line, 6);
+        assert_eq!(
+            error.code.to_string(),
+            "ERROR 125: No coinciden los tipos, e es de tipo Entero y el valor es de tipo Real."
+        );
+    }
+
+    #[test]
+    fn declared_parameters() {
+        let functions = "Funcion r <- Mitad(n)
+                Definir n Como Real
+                n <- n / 2
+                r <- n
+            FinFuncion
+            Funcion r <- Doble(n)
+                Definir n Como Entero
+                r <- n * 2
+            FinFuncion
+            SubProceso Sumar(total Por Referencia)
+                Definir total Como Real
+                total <- total + 0.5
+            FinSubProceso";
+        let run = |algorithm: &str| {
+            let (mut interpreter, algorithm) = interpreter_for(
+                &format!("{functions}\nAlgoritmo t\n{algorithm}\nFinAlgoritmo"),
+                10,
+                Profile::Flexible,
+            );
+            interpreter
+                .execute(&algorithm)
+                .map(|()| interpreter.memory.get("x").cloned())
+                .map_err(|error| error.code.to_string())
+        };
+
+        assert_eq!(
+            run("Definir x Como Real\nx <- Mitad(3)\nSumar(x)"),
+            Ok(Some(Value::Real(2.0)))
+        );
+        assert_eq!(
+            run("Definir x Como Entero\nx <- Doble(2.5)"),
+            Err(
+                "ERROR 125: No coinciden los tipos, n es de tipo Entero y el valor es de tipo Real."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            run("Definir x Como Entero\nSumar(x)"),
+            Err(
+                "ERROR 125: No coinciden los tipos, total es de tipo Real y el valor es de tipo Entero."
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn definir_inside_loops() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "Algoritmo t
+                Definir i Como Entero
+                Definir v Como Real
+                Dimension v[3], w[2]
+                Para i <- 1 Hasta 3 Hacer
+                    Definir suma Como Entero
+                    suma <- suma + i
+                    v[i] <- i
+                FinPara
+                Para Cada x De v Hacer
+                    Definir x Como Real
+                    x <- x / 2
+                FinPara
+                Para Cada elemento De w Hacer
+                    Definir elemento Como Real
+                FinPara
+                Repetir
+                    Definir n Como Entero
+                    n <- n + 1
+                Hasta Que n = 3
+            FinAlgoritmo",
+            10,
+            Profile::Flexible,
+        );
+
+        interpreter.execute(&algorithm).unwrap();
+        // running Definir again keeps the value the variable had
+        assert_eq!(interpreter.memory.get("suma"), Some(&Value::Entero(6)));
+        assert_eq!(interpreter.memory.get("n"), Some(&Value::Entero(3)));
+        assert_eq!(
+            interpreter.memory.get_element("v", &[3]).ok(),
+            Some(&Value::Real(1.5))
+        );
+        // an array without a type takes the one declared through its elements
+        assert_eq!(interpreter.memory.get_type("w"), Some(Type::Real));
+        assert_eq!(
+            interpreter.memory.get_element("w", &[2]).ok(),
+            Some(&Value::Real(0.0))
+        );
+    }
+
+    #[test]
+    fn uninitialized_variables() {
+        let code = "Algoritmo t
+                Definir a, b Como Entero
+                Definir v Como Real
+                Dimension v[3]
+                a <- 1
+                v[2] <- a
+                b <- a + v[2] + v[1]
+            FinAlgoritmo";
+
+        let (mut interpreter, algorithm) = interpreter_for(code, 10, Profile::Flexible);
+        interpreter.execute(&algorithm).unwrap();
+        assert_eq!(interpreter.memory.get("b"), Some(&Value::Entero(2)));
+
+        let (mut interpreter, algorithm) = interpreter_for(code, 10, Profile::Estricto);
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        assert_eq!(error.span.line, 7);
+        assert!(matches!(
+            error.code.error,
+            PossibleErrors::VariableNotFound(ref name) if name == "v"
+        ));
+    }
+
+    #[test]
+    fn expression_error_spans() {
+        // the line of the error and the code it points at
+        let error_at = |body: &str| {
+            let code = format!(
+                "Funcion r <- Mitad(n)
+                    r <- n / 0
+                FinFuncion
+                SubProceso Saludar
+                FinSubProceso
+                Algoritmo t
+                    Definir x Como Entero
+                    {body}
+                FinAlgoritmo"
+            );
+            let (mut interpreter, algorithm) = interpreter_for(&code, 10, Profile::Flexible);
+            let span = interpreter.execute(&algorithm).unwrap_err().span;
+
+            (span.line, code[span.start..span.end].to_string())
+        };
+
+        assert_eq!(error_at("x <- año + 1"), (8, "año".to_string()));
+        assert_eq!(error_at("x <- 1 + 'a' * 2"), (8, "*".to_string()));
+        assert_eq!(error_at("x <- 1 + Saludar()"), (8, "Saludar()".to_string()));
+        // an Entero that overflows is reported at the operator, not as a Real stored in x
+        assert_eq!(
+            error_at("x <- 9223372036854775807 + 1"),
+            (8, "+".to_string())
+        );
+        // inside a call the error points at the subproceso's own code
+        assert_eq!(error_at("Escribir 1 + Mitad(4)"), (2, "/".to_string()));
+        // errors that aren't about one token point at the whole statement
+        assert_eq!(error_at("x <- 'hola'"), (8, "x <- 'hola'".to_string()));
+    }
+
+    #[test]
+    fn case_insensitive_names() {
+        let (mut interpreter, algorithm) = interpreter_for(
+            "SubProceso Sumar(Total Por Referencia, n)
+                TOTAL <- total + N
+            FinSubProceso
+            Algoritmo t
+                Definir Suma Como Entero
+                Dimension suma[2]
+                SUMA[1] <- 4
+                sumar(Suma[1], 3)
+                Escribir Resta
+            FinAlgoritmo",
+            10,
+            Profile::Flexible,
+        );
+
+        let error = interpreter.execute(&algorithm).unwrap_err();
+        assert_eq!(
+            interpreter.memory.get_element("suma", &[1]).ok(),
+            Some(&Value::Entero(7))
+        );
+        assert_eq!(
+            error.code.to_string(),
+            "ERROR 215: Variable no inicializada (Resta)"
+        );
+    }
+
+    #[test]
+    fn typed_input() {
+        assert_eq!(parse_input("42\n", Type::Entero), Some(Value::Entero(42)));
+        assert_eq!(parse_input("4.5", Type::Entero), None);
+        assert_eq!(parse_input("4.5", Type::Real), Some(Value::Real(4.5)));
+        assert_eq!(
+            parse_input("Verdadero", Type::Logico),
+            Some(Value::Logico(true))
+        );
+        assert_eq!(parse_input("si", Type::Logico), None);
+        assert_eq!(parse_input("4.5\n", Type::None), Some(Value::Real(4.5)));
+        for text in ["nan", "inf", "-infinity", "NaN", "1e999"] {
+            assert_eq!(parse_input(text, Type::Real), None);
+            assert_eq!(
+                parse_input(text, Type::None),
+                Some(Value::Caracter(text.to_string()))
+            );
+        }
+        assert_eq!(
+            parse_input("hola mundo\n", Type::Caracter),
+            Some(Value::Caracter("hola mundo".to_string()))
+        );
     }
 }
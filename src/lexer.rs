@@ -1,33 +1,96 @@
 use std::{iter::Peekable, str::Chars};
 
-use crate::tokens::{convert_to_keyword, Token};
+use crate::error::{Code, PossibleErrors};
+use crate::span::{Span, Spanned};
+use crate::tokens::{convert_to_keyword, Keyword, Token, COMPOUND_KEYWORDS};
+
+/// Walks the source one character at a time, keeping track of where it is.
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+    offset: usize,
+}
 
-pub struct Lexer {}
+impl Cursor<'_> {
+    fn new(code: &str) -> Cursor<'_> {
+        Cursor {
+            chars: code.chars().peekable(),
+            line: 1,
+            column: 1,
+            offset: 0,
+        }
+    }
 
-impl Lexer {
-    fn parse_numeric(initial_char: char, chars: &mut Peekable<Chars>) -> Token {
-        let mut curr_char = initial_char;
-        let mut string = String::new();
+    fn next(&mut self) -> Option<char> {
+        let ch = self.chars.next()?;
 
-        while curr_char.is_numeric() | curr_char.eq(&'.') {
-            string.push(curr_char);
+        self.offset += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
 
-            // No more characters, this mean the line of code has reached the end.
-            let next_char = chars.peek();
-            if !next_char.is_some_and(|f| f.is_numeric() || f.eq(&'.')) {
-                break;
-            };
+        Some(ch)
+    }
 
-            curr_char = chars.next().unwrap();
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    fn next_if(&mut self, func: impl FnOnce(&char) -> bool) -> Option<char> {
+        if self.peek().is_some_and(func) {
+            return self.next();
+        }
+        None
+    }
+
+    fn next_if_eq(&mut self, expected: char) -> Option<char> {
+        self.next_if(|ch| *ch == expected)
+    }
+
+    /// An empty span at the current position, to be stretched once a token is read.
+    fn location(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+            start: self.offset,
+            end: self.offset,
         }
+    }
+}
+
+/// Whether `ch` can be part of a name once it has started.
+fn is_identifier_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+pub struct Lexer {}
 
-        if string.matches('.').count() == 1 {
-            return Token::Numero(string.parse().unwrap(), false);
+impl Lexer {
+    fn parse_numeric(initial_char: char, chars: &mut Cursor) -> Result<Token, Code> {
+        let mut string = String::from(initial_char);
+
+        while let Some(ch) = chars.next_if(|f| f.is_numeric() || f.eq(&'.')) {
+            string.push(ch);
         }
-        Token::Numero(string.parse().unwrap(), true)
+
+        // `is_numeric` lets through digits of other scripts that can't be parsed
+        let token = match string.matches('.').count() {
+            0 => string.parse().ok().map(Token::Entero),
+            1 => string.parse().ok().map(Token::Real),
+            _ => None,
+        };
+
+        token.ok_or(Code {
+            error: PossibleErrors::InvalidNumber(string),
+        })
     }
 
-    fn parse_alphanumeric(initial_char: char, chars: &mut Peekable<Chars>) -> Token {
+    fn parse_alphanumeric(initial_char: char, chars: &mut Cursor) -> Token {
         let mut curr_char = initial_char;
         let mut string = String::new();
 
@@ -35,9 +98,9 @@ impl Lexer {
             string.push(curr_char);
 
             // No more characters, this mean the line of code has reached the end.
-            if chars.peek().is_none() || chars.peek().is_some_and(|f| !f.is_alphanumeric()) {
+            if chars.peek().is_none_or(|f| !is_identifier_char(*f)) {
                 break;
-            };
+            }
 
             curr_char = chars.next().unwrap();
         }
@@ -45,68 +108,119 @@ impl Lexer {
         convert_to_keyword(string)
     }
 
-    fn parse_string(quote: char, chars: &mut Peekable<Chars>) -> Token {
-        // skips first quote
-        let mut curr_char = chars.next().unwrap();
+    /// Reads the next word on the same line, skipping the whitespace before it.
+    fn next_word(chars: &mut Cursor) -> Option<String> {
+        while chars.next_if(|f| f.is_whitespace() && *f != '\n').is_some() {}
+
+        let mut word = String::new();
+        while let Some(ch) = chars.next_if(|f| is_identifier_char(*f)) {
+            word.push(ch);
+        }
+
+        if word.is_empty() {
+            return None;
+        }
+        Some(word)
+    }
+
+    /// Joins `keyword` with the words that follow it when they make up a compound keyword
+    /// such as "Hasta Que", only advancing `chars` if they do.
+    fn parse_compound_keyword(keyword: Keyword, chars: &mut Cursor) -> Option<Keyword> {
+        for (first, words, compound) in COMPOUND_KEYWORDS {
+            if *first != keyword {
+                continue;
+            }
+
+            let mut lookahead = chars.clone();
+            let matches = words.iter().all(|word| {
+                Lexer::next_word(&mut lookahead).is_some_and(|next| next.eq_ignore_ascii_case(word))
+            });
+
+            if matches {
+                *chars = lookahead;
+                return Some(*compound);
+            }
+        }
+
+        None
+    }
+
+    /// Reads a string up to its closing `quote`, which has to be on the same line.
+    fn parse_string(quote: char, chars: &mut Cursor) -> Result<Token, Code> {
         let mut string = String::new();
 
-        while curr_char != quote {
-            string.push(curr_char);
-            curr_char = chars.next().unwrap();
+        while let Some(ch) = chars.next_if(|f| *f != quote && *f != '\n') {
+            string.push(ch);
         }
 
-        Token::String(string)
+        if chars.next_if_eq(quote).is_none() {
+            return Err(Code {
+                error: PossibleErrors::UnterminatedString,
+            });
+        }
+        Ok(Token::String(string))
     }
 
-    pub fn lex(code: &str) -> Vec<Token> {
-        let mut tokens: Vec<Token> = Vec::new();
-        let mut chars = code.chars().peekable();
+    /// Lexes a whole file, every token knows where it was written. Lexing goes on after
+    /// an error so all of them can be reported at once.
+    pub fn lex_file(code: &str) -> Result<Vec<Spanned<Token>>, Vec<Spanned<Code>>> {
+        let mut tokens: Vec<Spanned<Token>> = Vec::new();
+        let mut errors: Vec<Spanned<Code>> = Vec::new();
+        let mut chars = Cursor::new(code);
 
-        // if iterator still has content
-        while let Some(curr_char) = chars.next() {
-            match curr_char {
+        loop {
+            let mut span = chars.location();
+            let Some(curr_char) = chars.next() else {
+                break;
+            };
+
+            let token = match curr_char {
                 '=' => {
                     let next_char = chars.peek().unwrap_or(&' ');
 
                     if next_char == &'=' {
-                        tokens.push(Token::Comparacion);
                         chars.next();
+                        Ok(Token::Comparacion)
                     } else {
-                        tokens.push(Token::Igual);
+                        Ok(Token::Igual)
                     }
                 }
 
-                '+' => tokens.push(Token::Suma),
-                ',' => tokens.push(Token::SeparadorArgumento),
-                '-' => {
-                    if tokens.last().is_some() && tokens.last().unwrap() == &Token::Igual {
-                        let ch = chars.peek().unwrap().to_owned();
-                        chars.next();
-                        let token = Lexer::parse_numeric(ch, &mut chars);
-                        if let Token::Numero(i, rounded) = token {
-                            tokens.push(Token::Numero(-i, rounded));
-                        }
-                    } else {
-                        tokens.push(Token::Resta);
+                '+' => Ok(Token::Suma),
+                ',' => Ok(Token::SeparadorArgumento),
+                '-' if tokens.last().is_some_and(|last| last.node == Token::Igual) => {
+                    match chars.next_if(|f| f.is_numeric()) {
+                        Some(ch) => match Lexer::parse_numeric(ch, &mut chars) {
+                            Ok(Token::Entero(num)) => Ok(Token::Entero(-num)),
+                            Ok(Token::Real(num)) => Ok(Token::Real(-num)),
+                            other => other,
+                        },
+                        None => Ok(Token::Resta),
                     }
                 }
-                '*' => tokens.push(Token::Multiplicacion),
-                '/' => tokens.push(Token::Division),
+                '-' => Ok(Token::Resta),
+                '*' => Ok(Token::Multiplicacion),
+                // comments run until the end of the line
+                '/' if chars.next_if_eq('/').is_some() => {
+                    while chars.next_if(|f| *f != '\n').is_some() {}
+                    continue;
+                }
+                '/' => Ok(Token::Division),
 
                 '<' => {
                     let next_char = chars.peek().unwrap_or(&' ');
 
                     if next_char == &'-' {
-                        tokens.push(Token::Igual);
                         chars.next();
+                        Ok(Token::Igual)
                     } else if next_char == &'=' {
-                        tokens.push(Token::MenorOIgual);
                         chars.next();
+                        Ok(Token::MenorOIgual)
                     } else if next_char == &'>' {
-                        tokens.push(Token::Diferente);
                         chars.next();
+                        Ok(Token::Diferente)
                     } else {
-                        tokens.push(Token::MenorA);
+                        Ok(Token::MenorA)
                     }
                 }
 
@@ -114,65 +228,207 @@ impl Lexer {
                     let next_char = chars.peek().unwrap_or(&' ');
 
                     if next_char == &'=' {
-                        tokens.push(Token::MayorOIgual);
                         chars.next();
+                        Ok(Token::MayorOIgual)
                     } else {
-                        tokens.push(Token::MayorA);
+                        Ok(Token::MayorA)
                     }
                 }
 
-                '(' => tokens.push(Token::AbrirParentesis),
-                ')' => tokens.push(Token::CerrarParentesis),
+                '(' => Ok(Token::AbrirParentesis),
+                ')' => Ok(Token::CerrarParentesis),
+                '[' => Ok(Token::AbrirCorchete),
+                ']' => Ok(Token::CerrarCorchete),
 
-                'Y' => tokens.push(Token::Y),
-                'y' => tokens.push(Token::Y),
+                ':' => Ok(Token::DosPuntos),
+                ';' => Ok(Token::EOL),
 
-                'O' => tokens.push(Token::O),
-                'o' => tokens.push(Token::O),
+                '\"' | '\'' => Lexer::parse_string(curr_char, &mut chars),
 
-                '\"' => {
-                    let token = Lexer::parse_string('\"', &mut chars);
-                    tokens.push(token);
-                }
+                ch if ch.is_numeric() => Lexer::parse_numeric(ch, &mut chars),
 
-                '\'' => {
-                    let token = Lexer::parse_string('\'', &mut chars);
-                    tokens.push(token);
-                }
+                ch if is_identifier_char(ch) => {
+                    let token = Lexer::parse_alphanumeric(ch, &mut chars);
 
-                ch if ch.is_numeric() => {
-                    let token = Lexer::parse_numeric(ch, &mut chars);
-                    tokens.push(token);
+                    match token {
+                        Token::Instruccion(keyword) => {
+                            match Lexer::parse_compound_keyword(keyword, &mut chars) {
+                                Some(compound) => Ok(Token::Instruccion(compound)),
+                                None => Ok(token),
+                            }
+                        }
+                        _ => Ok(token),
+                    }
                 }
 
-                ch if ch.is_alphanumeric() => {
-                    let token = Lexer::parse_alphanumeric(ch, &mut chars);
-                    tokens.push(token);
-                }
+                ch if ch.is_whitespace() => continue,
+                ch => Err(Code {
+                    error: PossibleErrors::InvalidCharacter(ch),
+                }),
+            };
 
-                _ => {
-                    // should error out, but we can ignore it for now
-                }
+            span.end = chars.offset;
+            match token {
+                Ok(token) => tokens.push(Spanned::new(token, span)),
+                Err(code) => errors.push(Spanned::new(code, span)),
             }
         }
 
-        tokens
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(tokens)
+    }
+
+    /// Lexes a single line, leaving out where each token was.
+    #[cfg(test)]
+    pub fn lex(code: &str) -> Vec<Token> {
+        Lexer::lex_file(code)
+            .expect("the code should lex")
+            .into_iter()
+            .map(|token| token.node)
+            .collect()
     }
 }
 
-pub fn find_algorithm(code: &str) -> (usize, usize) {
-    let lines = code.lines().map(str::to_ascii_lowercase);
-    let mut algo_start: usize = 0;
-    let mut algo_end: usize = 0;
-
-    // TODO: add error handling for files without starting or ending algorithm keywords
-    for (idx, line) in lines.enumerate() {
-        if line.starts_with("algoritmo") {
-            algo_start = idx;
-        } else if line.starts_with("finalgoritmo") {
-            algo_end = idx;
+/// Splits the tokens of a whole file into its statements, one per line unless a `;`
+/// separates them, leaving out the empty ones.
+pub fn split_lines(tokens: Vec<Spanned<Token>>) -> Vec<Vec<Spanned<Token>>> {
+    let mut lines: Vec<Vec<Spanned<Token>>> = Vec::new();
+    let mut separated = false;
+
+    for token in tokens {
+        if token.node == Token::EOL {
+            separated = true;
+            continue;
         }
+
+        match lines.last_mut() {
+            Some(line) if !separated && line[0].span.line == token.span.line => line.push(token),
+            _ => lines.push(vec![token]),
+        }
+        separated = false;
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod lexer_tests {
+    use super::*;
+
+    #[test]
+    fn comments() {
+        let tokens = Lexer::lex("a <- 4 / 2 // divide \"a\" por 2");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Variable("a".to_string()),
+                Token::Igual,
+                Token::Entero(4),
+                Token::Division,
+                Token::Entero(2),
+            ]
+        );
+
+        let tokens = Lexer::lex("Escribir \"http://pseint\" // link");
+        assert_eq!(tokens[1], Token::String("http://pseint".to_string()));
+        assert_eq!(tokens.len(), 2);
+
+        assert!(Lexer::lex("// solo un comentario").is_empty());
     }
 
-    (algo_start, algo_end)
+    #[test]
+    fn compound_keywords_need_whole_words() {
+        assert_eq!(
+            Lexer::lex("Para i <- 1 Hasta que_max Hacer"),
+            vec![
+                Token::Instruccion(Keyword::Para),
+                Token::Variable("i".to_string()),
+                Token::Igual,
+                Token::Entero(1),
+                Token::Instruccion(Keyword::Hasta),
+                Token::Variable("que_max".to_string()),
+                Token::Instruccion(Keyword::Hacer),
+            ]
+        );
+        assert_eq!(
+            Lexer::lex("Mientras que_flag Hacer"),
+            vec![
+                Token::Instruccion(Keyword::Mientras),
+                Token::Variable("que_flag".to_string()),
+                Token::Instruccion(Keyword::Hacer),
+            ]
+        );
+        assert_eq!(
+            Lexer::lex("Hasta Que fin"),
+            vec![
+                Token::Instruccion(Keyword::HastaQue),
+                Token::Variable("fin".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_literals() {
+        let errors = Lexer::lex_file("a <- 1.2.3\nEscribir \"hola\nb <- 2 # 3").unwrap_err();
+
+        let errors: Vec<(String, usize, usize)> = errors
+            .iter()
+            .map(|error| (error.node.to_string(), error.span.line, error.span.column))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "ERROR: Constante numérica no válida (1.2.3).".to_string(),
+                    1,
+                    6
+                ),
+                ("ERROR: Falta cerrar la cadena de texto.".to_string(), 2, 10),
+                ("ERROR: Caracter no válido (#).".to_string(), 3, 8),
+            ]
+        );
+
+        assert_eq!(
+            Lexer::lex("a <- -"),
+            vec![Token::Variable("a".to_string()), Token::Igual, Token::Resta]
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let tokens = Lexer::lex_file("Algoritmo año\n\tx <- \"ñ\" // c\nFinAlgoritmo").unwrap();
+
+        let spans: Vec<(usize, usize, usize, usize)> = tokens
+            .iter()
+            .map(|token| {
+                let span = token.span;
+                (span.line, span.column, span.start, span.end)
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (1, 1, 0, 9),
+                (1, 11, 10, 14),
+                (2, 2, 16, 17),
+                (2, 4, 18, 20),
+                (2, 7, 21, 25),
+                (3, 1, 31, 43)
+            ]
+        );
+
+        let lines = split_lines(tokens);
+        assert_eq!(
+            lines.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![2, 3, 1]
+        );
+
+        let lines = split_lines(Lexer::lex_file("a <- 1; b <- 2;\nEscribir a;;").unwrap());
+        assert_eq!(
+            lines.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![3, 3, 2]
+        );
+    }
 }
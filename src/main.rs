@@ -1,13 +1,14 @@
 #![deny(clippy::pedantic)]
 
-use clap::Parser;
-use log::{debug, info};
-use std::path::PathBuf;
+use clap::{builder::RangedU64ValueParser, Parser};
+use std::{path::PathBuf, process, thread};
 
 use crate::{
-    ast::build_ast,
-    interpreter::Interpreter,
-    lexer::{find_algorithm, Lexer},
+    ast::build_program,
+    error::{report, Code},
+    interpreter::{Interpreter, Profile},
+    lexer::{split_lines, Lexer},
+    span::Spanned,
 };
 
 mod ast;
@@ -17,11 +18,40 @@ mod interpreter;
 mod lexer;
 mod memory;
 mod parser;
+mod span;
 mod tokens;
+mod value;
+
+/// Stack reserved for each nested subproceso call, every one of them goes through
+/// several Rust frames while running.
+const STACK_PER_CALL: usize = 64 * 1024;
+const BASE_STACK: usize = 8 * 1024 * 1024;
+/// The deepest `--max-depth` accepted, past it the stack needed couldn't be reserved.
+const MAX_DEPTH: u64 = 10_000;
 
 #[derive(Parser, Debug)]
 struct Args {
     path: PathBuf,
+    /// How many subproceso calls can be nested before giving up, recursion included.
+    #[arg(
+        long,
+        default_value_t = 1000,
+        value_parser = RangedU64ValueParser::<usize>::new().range(0..=MAX_DEPTH),
+    )]
+    max_depth: usize,
+    /// The strict profile doesn't give variables a value until they are assigned.
+    #[arg(long, value_enum, default_value_t = Profile::Flexible)]
+    profile: Profile,
+}
+
+/// Prints every error found before running and gives up.
+fn fail(source: &str, errors: &[Spanned<Code>]) -> ! {
+    let reports: Vec<String> = errors
+        .iter()
+        .map(|error| report(source, &error.node, error.span))
+        .collect();
+    eprintln!("{}", reports.join("\n\n"));
+    process::exit(1);
 }
 
 fn main() {
@@ -30,21 +60,43 @@ fn main() {
     let args = Args::parse();
 
     let content = file::open(args.path);
-    let (algo_start, algo_end) = find_algorithm(&content);
-    let lines: Vec<&str> = content.lines().collect();
 
-    let lines_of_code = lines[1 + algo_start..algo_end].to_vec();
+    let tokens = match Lexer::lex_file(&content) {
+        Ok(tokens) => tokens,
+        Err(errors) => fail(&content, &errors),
+    };
 
-    let code = lines_of_code
-        .iter()
-        .map(|f| Lexer::lex(f))
-        .filter(|f| !f.is_empty())
-        .collect();
+    let program = match build_program(split_lines(tokens)) {
+        Ok(program) => program,
+        Err(errors) => fail(&content, &errors),
+    };
+    // debug!("{:#?}", program);
+
+    // the interpreter recurses on every call, so it gets a stack big enough for
+    // max_depth calls instead of crashing before reaching the limit
+    let max_depth = args.max_depth;
+    let profile = args.profile;
+    let stack_size = max_depth
+        .saturating_mul(STACK_PER_CALL)
+        .saturating_add(BASE_STACK);
+    let runner = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || {
+            let mut interpreter = Interpreter::new(program.functions, max_depth, profile);
+            interpreter.execute(&program.algorithm)
+        });
+    let runner = match runner {
+        Ok(runner) => runner,
+        Err(error) => {
+            eprintln!("No se pudo iniciar el intérprete: {error}");
+            process::exit(1);
+        }
+    };
 
-    let mut interpreter = Interpreter::new(&lines_of_code);
-    let ast = build_ast(code).unwrap();
-    // debug!("{:#?}", ast);
-    interpreter.run(ast);
+    if let Err(error) = runner.join().expect("The interpreter crashed.") {
+        eprintln!("{}", error.report(&content));
+        process::exit(1);
+    }
 
     // let tokens = Lexer::lex("(5*4+3*2)-1".to_string());
     // let postfix = shunting_yard(tokens);
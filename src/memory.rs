@@ -1,86 +1,449 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::HashMap;
 use std::fmt;
 
 use crate::{
     error::{Code, PossibleErrors},
-    tokens::{Token, Type},
+    tokens::{identifier_key, Type},
+    value::{Reference, Value},
 };
 
+/// What a name holds along with the type it was declared with, for an array the type of
+/// its elements. The value is `None` until assigned when variables don't start with a
+/// default. A `Type::None` slot wasn't declared yet, the first value assigned to it fixes
+/// its type.
+#[derive(Debug, Clone)]
+pub struct Slot {
+    tipo: Type,
+    value: Option<Value>,
+}
+
+impl Slot {
+    /// A slot for a value that wasn't declared with `Definir`, such as a parameter
+    /// passed by value, its type is the one of the value until a `Definir` changes it.
+    pub fn from_value(value: Value) -> Slot {
+        Slot {
+            tipo: value.tipo(),
+            value: Some(value),
+        }
+    }
+
+    /// A slot for a variable that exists before being declared, such as the return
+    /// variable of a subproceso.
+    pub fn untyped() -> Slot {
+        Slot {
+            tipo: Type::None,
+            value: None,
+        }
+    }
+
+    /// A slot standing for whatever `reference` points at.
+    fn alias(reference: Reference) -> Slot {
+        Slot {
+            tipo: Type::None,
+            value: Some(Value::Referencia(reference)),
+        }
+    }
+
+    /// Whether the slot holds a single value, assigned or not.
+    fn is_single(&self) -> bool {
+        !matches!(
+            self.value,
+            Some(Value::Arreglo { .. } | Value::Referencia(_))
+        )
+    }
+
+    /// The dimensions and elements of the array the slot holds.
+    fn array(&self) -> Option<(&[usize], &[Option<Value>])> {
+        match &self.value {
+            Some(Value::Arreglo { dimensions, values }) => Some((dimensions, values)),
+            _ => None,
+        }
+    }
+
+    /// The type of the array the slot holds along with one of its elements.
+    fn element_mut(&mut self, element: usize) -> Option<(&mut Type, &mut Option<Value>)> {
+        match &mut self.value {
+            Some(Value::Arreglo { values, .. }) => Some((&mut self.tipo, values.get_mut(element)?)),
+            _ => None,
+        }
+    }
+}
+
+/// The most elements an array can hold counting every dimension, so a `Dimension` with
+/// huge sizes is an error instead of running out of memory.
+const MAX_ELEMENTS: usize = 10_000_000;
+
+/// Variables are kept in frames, each subproceso call pushes its own so
+/// it can only see its parameters and the variables it defines. Names are looked up
+/// ignoring case, errors keep the spelling they were given.
 pub struct Memoria {
-    memory: HashMap<String, Token>,
+    frames: Vec<HashMap<String, Slot>>,
+    /// Whether variables start with their type's default value, otherwise reading one
+    /// before assigning it is an error.
+    defaults: bool,
+}
+
+/// Converts `value` so it can be stored in `name`, declared as `tipo`.
+fn coerce(name: &str, tipo: Type, value: Value) -> Result<Value, Code> {
+    value.convert(tipo).map_err(|value| Code {
+        error: PossibleErrors::WrongType(name.to_string(), tipo, value.type_name()),
+    })
 }
 
 impl Memoria {
     pub fn new() -> Memoria {
-        let memory = HashMap::new();
-        Self { memory }
-    }
-
-    pub fn create(&mut self, name: String, tipo: Type) -> Option<()> {
-        match self.memory.entry(name) {
-            Entry::Occupied(_) => None,
-            Entry::Vacant(entry) => {
-                let initial_data = match tipo {
-                    // default values straight from PSeInt
-                    Type::Caracter => Token::String(String::new()),
-                    Type::Entero => Token::Numero(0.0, true),
-                    Type::Real => Token::Numero(0.0, false),
-                    Type::Logico => Token::Boolean(false),
-                    Type::None => return None,
-                };
-
-                entry.insert(initial_data);
-                Some(())
+        let frames = vec![HashMap::new()];
+        Self {
+            frames,
+            defaults: true,
+        }
+    }
+
+    /// Memory where variables have no value until they are assigned or read with `Leer`.
+    pub fn strict() -> Memoria {
+        Self {
+            defaults: false,
+            ..Memoria::new()
+        }
+    }
+
+    fn variables_mut(&mut self) -> &mut HashMap<String, Slot> {
+        self.frames
+            .last_mut()
+            .expect("the main algorithm's frame is never popped")
+    }
+
+    pub fn push_frame(&mut self) {
+        self.frames.push(HashMap::new());
+    }
+
+    pub fn pop_frame(&mut self) {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+        }
+    }
+
+    /// Follows `name` in the current frame to where its value is stored.
+    fn locate(&self, name: &str) -> Option<Reference> {
+        let frame = self.frames.len() - 1;
+        let key = identifier_key(name);
+
+        match &self.frames[frame].get(&key)?.value {
+            Some(Value::Referencia(reference)) => Some(reference.clone()),
+            _ => Some(Reference {
+                frame,
+                name: key,
+                element: None,
+            }),
+        }
+    }
+
+    /// The slot holding `name`'s value, and the element inside it when `name` aliases one.
+    fn slot(&self, name: &str) -> Option<(&Slot, Option<usize>)> {
+        let reference = self.locate(name)?;
+        let slot = self.frames[reference.frame].get(&reference.name)?;
+
+        Some((slot, reference.element))
+    }
+
+    fn slot_mut(&mut self, name: &str) -> Option<(&mut Slot, Option<usize>)> {
+        let reference = self.locate(name)?;
+        let slot = self.frames[reference.frame].get_mut(&reference.name)?;
+
+        Some((slot, reference.element))
+    }
+
+    /// Declares `name` as `tipo` in the current frame. A name that is there without being
+    /// declared, such as a parameter or an array dimensioned first, takes the type and
+    /// keeps its value converted to it, so declaring it again with the same type, as a
+    /// `Definir` inside a loop does, changes nothing. An alias declares the variable it
+    /// stands for, which keeps the type it already has.
+    pub fn create(&mut self, name: &str, tipo: Type) -> Result<(), Code> {
+        let Some(initial) = Value::initial(tipo) else {
+            return Err(Code {
+                error: PossibleErrors::MissingTypeOrUnvalidType,
+            });
+        };
+        let initial = Some(initial).filter(|_| self.defaults);
+
+        let convert = |value: &mut Option<Value>| {
+            *value = match value.take() {
+                Some(value) => Some(coerce(name, tipo, value)?),
+                None => initial.clone(),
+            };
+            Ok(())
+        };
+
+        let key = identifier_key(name);
+        let is_alias = matches!(
+            self.variables_mut().get(&key),
+            Some(Slot {
+                value: Some(Value::Referencia(_)),
+                ..
+            })
+        );
+        let Some((slot, _)) = self.slot_mut(name) else {
+            let value = initial.clone();
+            self.variables_mut().insert(key, Slot { tipo, value });
+            return Ok(());
+        };
+
+        if is_alias && slot.tipo != Type::None {
+            if slot.tipo == tipo {
+                return Ok(());
             }
+
+            return Err(Code {
+                error: PossibleErrors::WrongType(name.to_string(), tipo, slot.tipo.to_string()),
+            });
         }
+
+        match &mut slot.value {
+            Some(Value::Arreglo { values, .. }) => values.iter_mut().try_for_each(convert)?,
+            value => convert(value)?,
+        }
+        slot.tipo = tipo;
+
+        Ok(())
     }
 
-    pub fn get_type(&self, name: String) -> Option<Type> {
-        let data = self.memory.get(&name)?;
+    /// Turns a variable into an array of its type, every element starting at the type's
+    /// default value. An array dimensioned before it's declared gets its type from
+    /// `Definir` or from the first element assigned.
+    pub fn dimension(&mut self, name: &str, dimensions: Vec<usize>) -> Result<(), Code> {
+        let size = dimensions
+            .iter()
+            .try_fold(1usize, |size, dimension| size.checked_mul(*dimension))
+            .filter(|size| (1..=MAX_ELEMENTS).contains(size));
+        let Some(size) = size.filter(|_| !dimensions.is_empty()) else {
+            return Err(Code {
+                error: PossibleErrors::InvalidDimension(name.to_string()),
+            });
+        };
 
-        match data {
-            Token::Numero(_, rounded) => {
-                if *rounded {
-                    return Some(Type::Entero);
-                }
-                Some(Type::Real)
+        let tipo = match self.slot(name) {
+            None => Type::None,
+            Some((slot, None)) if slot.is_single() => slot.tipo,
+            Some(_) => {
+                return Err(Code {
+                    error: PossibleErrors::ArrayAlreadyDimensioned(name.to_string()),
+                })
+            }
+        };
+
+        let initial = Value::initial(tipo).filter(|_| self.defaults);
+        let array = Slot {
+            tipo,
+            value: Some(Value::Arreglo {
+                dimensions,
+                values: vec![initial; size],
+            }),
+        };
+
+        match self.slot_mut(name) {
+            Some((slot, _)) => *slot = array,
+            None => {
+                self.variables_mut().insert(identifier_key(name), array);
             }
-            Token::String(_) => Some(Type::Caracter),
-            Token::Boolean(_) => Some(Type::Logico),
-            _ => None,
         }
+
+        Ok(())
     }
 
-    pub fn get(&self, name: String) -> Option<&Token> {
-        let data = self.memory.get(&name);
+    /// The type `name` was declared with, for an array the type of its elements.
+    pub fn get_type(&self, name: &str) -> Option<Type> {
+        self.slot(name).map(|(slot, _)| slot.tipo)
+    }
 
-        data
+    pub fn is_array(&self, name: &str) -> bool {
+        matches!(self.slot(name), Some((slot, None)) if slot.array().is_some())
     }
 
-    pub fn set(&mut self, name: String, value: Token) -> Result<(), Code> {
-        match self.memory.entry(name) {
-            Entry::Occupied(mut entry) => {
-                if !(std::mem::discriminant(entry.get()) == std::mem::discriminant(&value)) {
-                    return Err(Code {
-                        error: crate::error::PossibleErrors::WrongType,
-                    });
-                }
-                entry.insert(value);
-                Ok(())
+    /// The value of `name`, `None` when it doesn't exist, is an array or hasn't been
+    /// assigned yet.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self.slot(name)? {
+            (slot, None) if slot.is_single() => slot.value.as_ref(),
+            (slot, Some(element)) => slot.array()?.1.get(element)?.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, name: String, value: Value) -> Result<(), Code> {
+        let place = match self.slot_mut(&name) {
+            Some((slot, None)) if slot.is_single() => Some((&mut slot.tipo, &mut slot.value)),
+            Some((slot, Some(element))) => slot.element_mut(element),
+            Some(_) => None,
+            None => {
+                return Err(Code {
+                    error: PossibleErrors::VariableNotFound(name),
+                })
             }
-            Entry::Vacant(entry) => {
+        };
+        let Some((tipo, current)) = place else {
+            return Err(Code {
+                error: PossibleErrors::WrongIndexCount(name),
+            });
+        };
+
+        let value = coerce(&name, *tipo, value)?;
+        *tipo = value.tipo();
+        *current = Some(value);
+        Ok(())
+    }
+
+    /// Converts 1-based `indices` into the position of the element inside the array's values.
+    fn offset(&self, name: &str, indices: &[i64]) -> Result<usize, Code> {
+        let dimensions = match self.slot(name) {
+            Some((slot, None)) => slot.array().map(|(dimensions, _)| dimensions),
+            Some(_) => None,
+            None => {
                 return Err(Code {
-                    error: PossibleErrors::VariableNotFound(entry.key().clone()),
-                });
+                    error: PossibleErrors::VariableNotFound(name.to_string()),
+                })
             }
+        };
+        let Some(dimensions) = dimensions else {
+            return Err(Code {
+                error: PossibleErrors::NotAnArray(name.to_string()),
+            });
+        };
+
+        if indices.len() != dimensions.len() {
+            return Err(Code {
+                error: PossibleErrors::WrongIndexCount(name.to_string()),
+            });
+        }
+
+        let mut offset = 0;
+        for (index, size) in indices.iter().zip(dimensions) {
+            let position = index
+                .checked_sub(1)
+                .and_then(|position| usize::try_from(position).ok())
+                .filter(|position| position < size)
+                .ok_or_else(|| Code {
+                    error: PossibleErrors::IndexOutOfBounds(name.to_string()),
+                })?;
+
+            offset = offset * size + position;
+        }
+
+        Ok(offset)
+    }
+
+    pub fn get_element(&self, name: &str, indices: &[i64]) -> Result<&Value, Code> {
+        let offset = self.offset(name, indices)?;
+
+        let (_, values) = self
+            .slot(name)
+            .and_then(|(slot, _)| slot.array())
+            .ok_or_else(|| Code {
+                error: PossibleErrors::NotAnArray(name.to_string()),
+            })?;
+
+        values[offset].as_ref().ok_or(Code {
+            error: PossibleErrors::VariableNotFound(name.to_string()),
+        })
+    }
+
+    /// Checks that `indices` point inside the array `name`, whether the element was
+    /// assigned or not.
+    pub fn check_indices(&self, name: &str, indices: &[i64]) -> Result<(), Code> {
+        self.offset(name, indices).map(|_| ())
+    }
+
+    pub fn set_element(&mut self, name: &str, indices: &[i64], value: Value) -> Result<(), Code> {
+        let offset = self.offset(name, indices)?;
+
+        let Some((tipo, current)) = self
+            .slot_mut(name)
+            .and_then(|(slot, _)| slot.element_mut(offset))
+        else {
+            return Err(Code {
+                error: PossibleErrors::NotAnArray(name.to_string()),
+            });
+        };
+
+        let value = coerce(name, *tipo, value)?;
+        *tipo = value.tipo();
+        *current = Some(value);
+        Ok(())
+    }
+
+    /// Amount of elements in an array, counting every dimension.
+    pub fn element_count(&self, name: &str) -> Result<usize, Code> {
+        match self.slot(name) {
+            Some((slot, None)) => slot.array().map(|(_, values)| values.len()),
+            _ => None,
+        }
+        .ok_or_else(|| Code {
+            error: PossibleErrors::NotAnArray(name.to_string()),
+        })
+    }
+
+    /// Builds an alias to `name`, or to the element at `indices` when there are any,
+    /// to be bound in a newer frame with `shadow`.
+    pub fn reference(&self, name: &str, indices: &[i64]) -> Result<Slot, Code> {
+        let mut reference = self.locate(name).ok_or_else(|| Code {
+            error: PossibleErrors::VariableNotFound(name.to_string()),
+        })?;
+
+        if !indices.is_empty() {
+            reference.element = Some(self.offset(name, indices)?);
+        }
+
+        Ok(Slot::alias(reference))
+    }
+
+    /// Builds an alias to the `n`th element of an array in row-major order.
+    pub fn nth_reference(&self, name: &str, n: usize) -> Result<Slot, Code> {
+        if n >= self.element_count(name)? {
+            return Err(Code {
+                error: PossibleErrors::IndexOutOfBounds(name.to_string()),
+            });
+        }
+
+        let mut reference = self.locate(name).ok_or_else(|| Code {
+            error: PossibleErrors::VariableNotFound(name.to_string()),
+        })?;
+        reference.element = Some(n);
+
+        Ok(Slot::alias(reference))
+    }
+
+    /// A copy of whatever `name` holds, following references.
+    pub fn copy(&self, name: &str) -> Option<Slot> {
+        match self.slot(name)? {
+            (slot, Some(element)) => Some(Slot {
+                tipo: slot.tipo,
+                value: slot.array()?.1.get(element)?.clone(),
+            }),
+            (slot, _) => Some(slot.clone()),
         }
     }
+
+    /// Binds `name` to `slot` in the current frame whether it existed or not, returning
+    /// what it held before so it can be put back with `restore`.
+    pub fn shadow(&mut self, name: &str, slot: Slot) -> Option<Slot> {
+        self.variables_mut().insert(identifier_key(name), slot)
+    }
+
+    pub fn restore(&mut self, name: &str, previous: Option<Slot>) {
+        let key = identifier_key(name);
+
+        match previous {
+            Some(slot) => self.variables_mut().insert(key, slot),
+            None => self.variables_mut().remove(&key),
+        };
+    }
 }
 
 impl fmt::Debug for Memoria {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (name, token) in &self.memory {
-            writeln!(f, "{} -> {:?}", name, token)?;
+        for (depth, frame) in self.frames.iter().enumerate() {
+            for (name, slot) in frame {
+                writeln!(f, "[{depth}] {name} -> {slot:?}")?;
+            }
         }
         Ok(())
     }
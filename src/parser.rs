@@ -1,211 +1,543 @@
+use std::fmt;
+
 use log::error;
 
 use crate::{
     error::{Code, PossibleErrors},
-    memory::Memoria,
+    span::{Span, Spanned},
     tokens::Token,
+    value::Value,
 };
 
-fn precedence(token: &Token) -> u8 {
-    match token {
-        Token::O => 1,
-        Token::Y => 2,
-        Token::Comparacion
-        | Token::MayorA
-        | Token::MayorOIgual
-        | Token::MenorA
-        | Token::MenorOIgual
-        | Token::Diferente
-        | Token::Igual => 3,
-        Token::Suma | Token::Resta => 4,
-        Token::Multiplicacion | Token::Division => 5,
-        _ => 0,
+/// The operators an expression can use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Y,
+    O,
+    Igual,
+    Diferente,
+    Comparacion,
+    MenorA,
+    MenorOIgual,
+    MayorA,
+    MayorOIgual,
+    Suma,
+    Resta,
+    Multiplicacion,
+    Division,
+}
+
+impl Operator {
+    fn from_token(token: &Token) -> Option<Operator> {
+        let operator = match token {
+            Token::Y => Operator::Y,
+            Token::O => Operator::O,
+            Token::Igual => Operator::Igual,
+            Token::Diferente => Operator::Diferente,
+            Token::Comparacion => Operator::Comparacion,
+            Token::MenorA => Operator::MenorA,
+            Token::MenorOIgual => Operator::MenorOIgual,
+            Token::MayorA => Operator::MayorA,
+            Token::MayorOIgual => Operator::MayorOIgual,
+            Token::Suma => Operator::Suma,
+            Token::Resta => Operator::Resta,
+            Token::Multiplicacion => Operator::Multiplicacion,
+            Token::Division => Operator::Division,
+            _ => return None,
+        };
+
+        Some(operator)
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            Operator::O => 1,
+            Operator::Y => 2,
+            Operator::Comparacion
+            | Operator::MayorA
+            | Operator::MayorOIgual
+            | Operator::MenorA
+            | Operator::MenorOIgual
+            | Operator::Diferente
+            | Operator::Igual => 3,
+            Operator::Suma | Operator::Resta => 4,
+            Operator::Multiplicacion | Operator::Division => 5,
+        }
+    }
+}
+
+/// Operators are written the way they are in the source, for error messages.
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Y => "Y",
+            Operator::O => "O",
+            Operator::Comparacion => "==",
+            Operator::MayorA => ">",
+            Operator::MayorOIgual => ">=",
+            Operator::MenorA => "<",
+            Operator::MenorOIgual => "<=",
+            Operator::Diferente => "<>",
+            Operator::Igual => "=",
+            Operator::Suma => "+",
+            Operator::Resta => "-",
+            Operator::Multiplicacion => "*",
+            Operator::Division => "/",
+        };
+
+        write!(f, "{symbol}")
     }
 }
 
-pub fn shunting_yard(expression: Vec<Token>, memory: &Memoria) -> Result<Vec<Token>, Code> {
-    let mut stack: Vec<Token> = Vec::new();
-    let mut queue: Vec<Token> = Vec::new();
+/// An operand whose value is only known while the algorithm runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lookup {
+    Variable(String),
+    /// `v[i, j]`, every index already in postfix order.
+    Element(String, Vec<Vec<Spanned<Postfix>>>),
+    /// `Nombre(a, b)`, every argument in postfix order. One that is only a variable or an
+    /// element can be passed by reference.
+    Call(String, Vec<Vec<Spanned<Postfix>>>),
+}
 
-    for token in expression {
-        match token {
-            Token::Numero(..) | Token::String(_) | Token::Boolean(_) => queue.push(token),
+/// An expression in postfix order, ready to be evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Postfix {
+    Operand(Value),
+    Lookup(Lookup),
+    Operator(Operator),
+}
 
-            Token::Variable(ref var_name) => match memory.get(var_name.clone()) {
-                Some(token) => queue.push(token.clone()),
-                None => {
-                    return Err(Code {
-                        error: PossibleErrors::VariableNotFound(var_name.clone()),
-                    });
-                }
-            },
+/// Splits a list of expressions on the commas that aren't nested inside parentheses or brackets.
+pub fn split_arguments(tokens: &[Spanned<Token>]) -> Vec<Vec<Spanned<Token>>> {
+    let mut arguments = vec![Vec::new()];
+    let mut depth = 0usize;
 
-            Token::AbrirParentesis | Token::Comparacion => stack.push(token),
-            Token::Suma
-            | Token::Resta
-            | Token::Multiplicacion
-            | Token::Division
-            | Token::MayorA
-            | Token::MayorOIgual
-            | Token::MenorA
-            | Token::MenorOIgual
-            | Token::Igual
-            | Token::Diferente
-            | Token::Y
-            | Token::O => {
-                while let Some(op) = stack.last() {
-                    if precedence(op) >= precedence(&token) {
-                        queue.push(stack.pop().unwrap());
-                    } else {
-                        break;
-                    }
+    for token in tokens {
+        match token.node {
+            Token::AbrirParentesis | Token::AbrirCorchete => depth += 1,
+            Token::CerrarParentesis | Token::CerrarCorchete => depth = depth.saturating_sub(1),
+            Token::SeparadorArgumento if depth == 0 => {
+                arguments.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+
+        arguments.last_mut().unwrap().push(token.clone());
+    }
+
+    arguments
+}
+
+fn find_closing(tokens: &[Spanned<Token>], open: &Token, close: &Token) -> Option<usize> {
+    let mut depth = 0usize;
+
+    for (idx, token) in tokens.iter().enumerate() {
+        if token.node == *open {
+            depth += 1;
+        } else if token.node == *close {
+            if depth == 0 {
+                return Some(idx);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
+}
+
+/// Finds the `]` closing a bracket that was opened right before `tokens`.
+pub fn closing_bracket(tokens: &[Spanned<Token>]) -> Option<usize> {
+    find_closing(tokens, &Token::AbrirCorchete, &Token::CerrarCorchete)
+}
+
+/// Finds the `)` closing a parenthesis that was opened right before `tokens`.
+pub fn closing_parenthesis(tokens: &[Spanned<Token>]) -> Option<usize> {
+    find_closing(tokens, &Token::AbrirParentesis, &Token::CerrarParentesis)
+}
+
+/// An error found at `span` of an expression.
+fn error_at(error: PossibleErrors, span: Span) -> Spanned<Code> {
+    Spanned::new(Code { error }, span)
+}
+
+/// The error for an expression that ended, or hit a `)`, right after `previous`
+/// when a value was expected. It points at `previous`.
+fn missing_operand(previous: Option<&Spanned<Token>>) -> Spanned<Code> {
+    let error = match previous.and_then(|token| Operator::from_token(&token.node)) {
+        Some(operator) => PossibleErrors::MissingOperandAfter(operator.to_string()),
+        None => PossibleErrors::MissingExpression,
+    };
+
+    error_at(error, previous.map(|token| token.span).unwrap_or_default())
+}
+
+/// Reads what follows the name of a variable or subproceso, `rest` being the tokens after
+/// it: an array element, a call or the variable itself. Returns how many tokens of `rest`
+/// were used along with it, the lookup spanning the name and everything used.
+fn parse_lookup(
+    name: &Spanned<String>,
+    rest: &[Spanned<Token>],
+) -> Result<(Spanned<Lookup>, usize), Spanned<Code>> {
+    let Some((open, rest)) = rest.split_first() else {
+        return Ok((
+            Spanned::new(Lookup::Variable(name.node.clone()), name.span),
+            0,
+        ));
+    };
+
+    match open.node {
+        // v[i, j]
+        Token::AbrirCorchete => {
+            let Some(close) = closing_bracket(rest) else {
+                return Err(error_at(
+                    PossibleErrors::MissingKeyword("]".to_string()),
+                    open.span,
+                ));
+            };
+
+            let indices = split_arguments(&rest[..close]);
+            if indices.iter().any(Vec::is_empty) {
+                return Err(error_at(PossibleErrors::MissingExpression, open.span));
+            }
+            let indices = indices
+                .iter()
+                .map(|index| shunting_yard(index))
+                .collect::<Result<Vec<Vec<Spanned<Postfix>>>, Spanned<Code>>>()?;
+
+            let span = name.span.to(rest[close].span);
+            let lookup = Lookup::Element(name.node.clone(), indices);
+            Ok((Spanned::new(lookup, span), close + 2))
+        }
+
+        // Nombre(a, b)
+        Token::AbrirParentesis => {
+            let Some(close) = closing_parenthesis(rest) else {
+                return Err(error_at(
+                    PossibleErrors::MissingClosingParenthesis,
+                    open.span,
+                ));
+            };
+
+            let arguments = match &rest[..close] {
+                [] => Vec::new(),
+                inner => split_arguments(inner),
+            };
+            if arguments.iter().any(Vec::is_empty) {
+                return Err(error_at(PossibleErrors::MissingExpression, open.span));
+            }
+            let arguments = arguments
+                .iter()
+                .map(|argument| shunting_yard(argument))
+                .collect::<Result<Vec<Vec<Spanned<Postfix>>>, Spanned<Code>>>()?;
+
+            let span = name.span.to(rest[close].span);
+            let lookup = Lookup::Call(name.node.clone(), arguments);
+            Ok((Spanned::new(lookup, span), close + 2))
+        }
+
+        _ => Ok((
+            Spanned::new(Lookup::Variable(name.node.clone()), name.span),
+            0,
+        )),
+    }
+}
+
+/// Turns an infix expression into postfix. Variables, array elements and calls are left
+/// to be looked up when it's evaluated. A `-` where a value is expected negates what
+/// follows it. Every item keeps the span of the tokens it came from, and so does any error.
+pub fn shunting_yard(
+    expression: &[Spanned<Token>],
+) -> Result<Vec<Spanned<Postfix>>, Spanned<Code>> {
+    let mut stack: Vec<&Spanned<Token>> = Vec::new();
+    let mut queue: Vec<Spanned<Postfix>> = Vec::new();
+    // true at the start, after an operator and after a `(`
+    let mut expect_operand = true;
+    let mut previous: Option<&Spanned<Token>> = None;
+
+    // an operator popped from the stack, `(` being the only token there that isn't one
+    let operator = |token: &Spanned<Token>| {
+        Operator::from_token(&token.node)
+            .map(|operator| Spanned::new(Postfix::Operator(operator), token.span))
+    };
+
+    let mut tokens = expression.iter();
+    while let Some(token) = tokens.next() {
+        let is_operand = matches!(
+            token.node,
+            Token::Entero(_)
+                | Token::Real(_)
+                | Token::String(_)
+                | Token::Boolean(_)
+                | Token::Variable(_)
+                | Token::AbrirParentesis
+        );
+        if is_operand && !expect_operand {
+            return Err(error_at(PossibleErrors::SyntaxError, token.span));
+        }
+
+        match &token.node {
+            Token::Entero(_) | Token::Real(_) | Token::String(_) | Token::Boolean(_) => {
+                queue.extend(
+                    Value::from_literal(&token.node)
+                        .map(|value| Spanned::new(Postfix::Operand(value), token.span)),
+                );
+            }
+
+            Token::Variable(name) => {
+                let name = Spanned::new(name.clone(), token.span);
+                let (lookup, used) = parse_lookup(&name, tokens.as_slice())?;
+                queue.push(Spanned::new(Postfix::Lookup(lookup.node), lookup.span));
+
+                // skip the indices or arguments along with their brackets
+                if used > 0 {
+                    tokens.nth(used - 1);
                 }
+            }
+
+            Token::AbrirParentesis => stack.push(token),
+
+            // -x is read as 0 - x, the `-` waits on the stack for x
+            Token::Resta if expect_operand => {
+                queue.push(Spanned::new(Postfix::Operand(Value::Entero(0)), token.span));
                 stack.push(token);
             }
 
             Token::CerrarParentesis => {
-                let mut curr_char = stack.pop().unwrap();
+                if expect_operand {
+                    return Err(missing_operand(previous));
+                }
 
-                while curr_char != Token::AbrirParentesis {
-                    queue.push(curr_char);
-                    curr_char = stack.pop().unwrap();
+                loop {
+                    match stack.pop() {
+                        Some(open) if open.node == Token::AbrirParentesis => break,
+                        Some(op) => queue.extend(operator(op)),
+                        None => {
+                            return Err(error_at(
+                                PossibleErrors::UnexpectedClosingParenthesis,
+                                token.span,
+                            ))
+                        }
+                    }
                 }
             }
 
-            _ => {
-                error!("token {token:?} shouldn't be here");
-                return Err(Code {
-                    error: PossibleErrors::InvalidInstruction,
-                });
+            node => {
+                let Some(current) = Operator::from_token(node) else {
+                    error!("token {node:?} shouldn't be here");
+                    return Err(error_at(PossibleErrors::InvalidInstruction, token.span));
+                };
+
+                if expect_operand {
+                    return Err(error_at(
+                        PossibleErrors::MissingOperandBefore(current.to_string()),
+                        token.span,
+                    ));
+                }
+
+                // an open parenthesis has no operator, so nothing is popped past it
+                while let Some(op) = stack.pop_if(|op| {
+                    Operator::from_token(&op.node)
+                        .is_some_and(|op| op.precedence() >= current.precedence())
+                }) {
+                    queue.extend(operator(op));
+                }
+                stack.push(token);
             }
+        }
+
+        expect_operand = match token.node {
+            Token::AbrirParentesis => true,
+            Token::CerrarParentesis => false,
+            _ => !is_operand,
         };
+        previous = Some(token);
     }
 
-    while !stack.is_empty() {
-        queue.push(stack.pop().unwrap());
+    if expect_operand {
+        return Err(missing_operand(previous));
+    }
+
+    while let Some(op) = stack.pop() {
+        let Some(item) = operator(op) else {
+            return Err(error_at(PossibleErrors::MissingClosingParenthesis, op.span));
+        };
+        queue.push(item);
     }
 
     Ok(queue)
 }
 
 struct CalcNode {
-    left: Token,
-    right: Token,
-    operator: Token,
+    left: Value,
+    right: Value,
+    operator: Operator,
 }
 
-impl CalcNode {
-    fn get_string_from_token(token: Token) -> Option<String> {
-        if let Token::String(i) = token {
-            return Some(i);
-        }
-        None
+fn wrong_type() -> Code {
+    Code {
+        error: PossibleErrors::TypeMismatch,
     }
+}
 
-    fn get_number_from_token(token: &Token) -> Option<(f32, bool)> {
-        if let Token::Numero(i, rounded) = token {
-            return Some((*i, *rounded));
-        }
-        None
-    }
+impl CalcNode {
+    /// Two Enteros give an Entero, unless the operator is `/`, which always gives a Real.
+    /// A result that doesn't fit in an Entero is an error.
+    fn calculate_integers(operator: Operator, left: i64, right: i64) -> Result<Value, Code> {
+        let result = match operator {
+            Operator::Suma => left.checked_add(right),
+            Operator::Resta => left.checked_sub(right),
+            Operator::Multiplicacion => left.checked_mul(right),
+            Operator::Comparacion | Operator::Igual => return Ok(Value::Logico(left == right)),
+            Operator::Diferente => return Ok(Value::Logico(left != right)),
+            Operator::MayorA => return Ok(Value::Logico(left > right)),
+            Operator::MayorOIgual => return Ok(Value::Logico(left >= right)),
+            Operator::MenorA => return Ok(Value::Logico(left < right)),
+            Operator::MenorOIgual => return Ok(Value::Logico(left <= right)),
+            _ => {
+                return CalcNode::calculate_reals(
+                    operator,
+                    &Value::Entero(left),
+                    &Value::Entero(right),
+                )
+            }
+        };
 
-    fn calculate_operation(result: f32) -> Token {
-        let is_rounded = result.fract() == 0.0;
-        Token::Numero(result, is_rounded)
+        result.map(Value::Entero).ok_or(Code {
+            error: PossibleErrors::IntegerOverflow,
+        })
     }
 
-    pub fn calculate(self) -> Option<Token> {
-        if !(std::mem::discriminant(&self.left) == std::mem::discriminant(&self.right)) {
-            return None;
+    /// Anything involving a Real is calculated as a Real.
+    #[allow(clippy::float_cmp)]
+    fn calculate_reals(operator: Operator, left: &Value, right: &Value) -> Result<Value, Code> {
+        let left = left.as_real().ok_or_else(wrong_type)?;
+        let right = right.as_real().ok_or_else(wrong_type)?;
+
+        match operator {
+            Operator::Suma => Ok(Value::Real(left + right)),
+            Operator::Resta => Ok(Value::Real(left - right)),
+            Operator::Multiplicacion => Ok(Value::Real(left * right)),
+            Operator::Division => {
+                if right == 0.0 {
+                    return Err(Code {
+                        error: PossibleErrors::DivisionByZero,
+                    });
+                }
+                Ok(Value::Real(left / right))
+            }
+            Operator::Comparacion => Ok(Value::Logico((left - right).abs() < 0.1)),
+            Operator::MayorA => Ok(Value::Logico(left > right)),
+            Operator::MayorOIgual => Ok(Value::Logico(left >= right)),
+            Operator::MenorA => Ok(Value::Logico(left < right)),
+            Operator::MenorOIgual => Ok(Value::Logico(left <= right)),
+            Operator::Diferente => Ok(Value::Logico(left != right)),
+            Operator::Igual => Ok(Value::Logico(left == right)),
+
+            _ => Err(wrong_type()),
         }
+    }
 
-        match self.left {
-            Token::Numero(_, _) => {
-                let (left, _) = CalcNode::get_number_from_token(&self.left).unwrap();
-                let (right, _) = CalcNode::get_number_from_token(&self.right).unwrap();
+    pub fn calculate(self) -> Result<Value, Code> {
+        let CalcNode {
+            left,
+            right,
+            operator,
+        } = self;
 
-                match self.operator {
-                    Token::Suma => {
-                        let result = left + right;
-                        Some(CalcNode::calculate_operation(result))
-                    }
-                    Token::Resta => {
-                        let result = left - right;
-                        Some(CalcNode::calculate_operation(result))
-                    }
-                    Token::Multiplicacion => {
-                        let result = left * right;
-                        Some(CalcNode::calculate_operation(result))
-                    }
-                    Token::Division => {
-                        let result = left / right;
-                        Some(CalcNode::calculate_operation(result))
-                    }
-                    Token::Comparacion => Some(Token::Boolean((left - right).abs() < 0.1)),
-                    Token::MayorA => Some(Token::Boolean(left > right)),
-                    Token::MayorOIgual => Some(Token::Boolean(left >= right)),
-                    Token::MenorA => Some(Token::Boolean(left < right)),
-                    Token::MenorOIgual => Some(Token::Boolean(left <= right)),
-                    Token::Diferente => Some(Token::Boolean(left != right)),
-                    Token::Igual => Some(Token::Boolean(left == right)),
-
-                    _ => None,
-                }
-            }
-            Token::String(_) => {
-                let left = CalcNode::get_string_from_token(self.left).unwrap();
-                let right = CalcNode::get_string_from_token(self.right).unwrap();
-
-                match self.operator {
-                    Token::Suma => Some(Token::String(left + &right)),
-                    Token::Comparacion => Some(Token::Boolean(left == right)),
-                    Token::Diferente => Some(Token::Boolean(left != right)),
-                    _ => None,
-                }
-            }
-            Token::Boolean(left) => {
-                if let Token::Boolean(right) = self.right {
-                    match self.operator {
-                        Token::Y => Some(Token::Boolean(left && right)),
-                        Token::O => Some(Token::Boolean(left || right)),
-                        Token::Igual => Some(Token::Boolean(left == right)),
-                        Token::Diferente => Some(Token::Boolean(left != right)),
-                        _ => None,
-                    }
-                } else {
-                    None
-                }
+        match (left, right) {
+            (Value::Entero(left), Value::Entero(right)) => {
+                CalcNode::calculate_integers(operator, left, right)
             }
-            _ => None,
+            (
+                left @ (Value::Entero(_) | Value::Real(_)),
+                right @ (Value::Entero(_) | Value::Real(_)),
+            ) => CalcNode::calculate_reals(operator, &left, &right),
+            (Value::Caracter(left), Value::Caracter(right)) => match operator {
+                Operator::Suma => Ok(Value::Caracter(left + &right)),
+                Operator::Comparacion | Operator::Igual => Ok(Value::Logico(left == right)),
+                Operator::Diferente => Ok(Value::Logico(left != right)),
+                _ => Err(wrong_type()),
+            },
+            (Value::Logico(left), Value::Logico(right)) => match operator {
+                Operator::Y => Ok(Value::Logico(left && right)),
+                Operator::O => Ok(Value::Logico(left || right)),
+                Operator::Igual => Ok(Value::Logico(left == right)),
+                Operator::Diferente => Ok(Value::Logico(left != right)),
+                _ => Err(wrong_type()),
+            },
+            _ => Err(wrong_type()),
         }
     }
 }
 
-pub fn postfix_stack_evaluator(tokens: Vec<Token>) -> Option<Token> {
-    let mut stack: Vec<Token> = Vec::new();
+/// Compares two values with the same semantics as `Comparacion` inside an expression,
+/// returns `None` when they can't be compared.
+pub fn compare_values(left: Value, right: Value) -> Option<bool> {
+    let node = CalcNode {
+        left,
+        right,
+        operator: Operator::Comparacion,
+    };
+
+    match node.calculate().ok()? {
+        Value::Logico(result) => Some(result),
+        _ => None,
+    }
+}
 
-    for token in tokens {
-        match token {
-            Token::Numero(..) | Token::String(_) | Token::Boolean(_) => stack.push(token),
-            operator => {
-                let right = stack.pop()?;
+/// Adds two numbers with the same rules as `+` inside an expression.
+pub fn add_numbers(left: Value, right: Value) -> Result<Value, Code> {
+    let node = CalcNode {
+        left,
+        right,
+        operator: Operator::Suma,
+    };
 
-                let left = stack.pop().unwrap_or(Token::Numero(0f32, false));
+    node.calculate()
+}
+
+/// Evaluates an expression in the postfix order `shunting_yard` leaves it in, getting the
+/// value of every variable, array element and call from `value_of` as they are reached,
+/// along with where they were written. An operator that fails points at itself.
+pub fn postfix_stack_evaluator(
+    postfix: &[Spanned<Postfix>],
+    mut value_of: impl FnMut(&Lookup, Span) -> Result<Value, Spanned<Code>>,
+) -> Result<Value, Spanned<Code>> {
+    let mut stack: Vec<Value> = Vec::new();
+
+    for item in postfix {
+        match &item.node {
+            Postfix::Operand(value) => stack.push(value.clone()),
+            Postfix::Lookup(lookup) => stack.push(value_of(lookup, item.span)?),
+            Postfix::Operator(operator) => {
+                let (Some(right), Some(left)) = (stack.pop(), stack.pop()) else {
+                    return Err(error_at(
+                        PossibleErrors::MissingOperandAfter(operator.to_string()),
+                        item.span,
+                    ));
+                };
 
                 let node = CalcNode {
                     left,
                     right,
-                    operator,
+                    operator: *operator,
                 };
-                let result = node.calculate();
-                result.as_ref()?;
-                stack.push(result?);
+                let value = node
+                    .calculate()
+                    .map_err(|code| Spanned::new(code, item.span))?;
+                stack.push(value);
             }
         }
     }
 
-    let result = stack.pop()?;
-    Some(result)
+    let end = postfix.last().map(|item| item.span).unwrap_or_default();
+    match (stack.pop(), stack.is_empty()) {
+        (Some(result), true) => Ok(result),
+        (None, _) => Err(error_at(PossibleErrors::MissingExpression, end)),
+        (Some(_), false) => Err(error_at(PossibleErrors::SyntaxError, end)),
+    }
 }
 
 #[cfg(test)]
@@ -214,78 +546,221 @@ mod parser_tests {
 
     use super::*;
 
+    fn lex(expression: &str) -> Vec<Spanned<Token>> {
+        Lexer::lex_file(expression).unwrap()
+    }
+
+    /// The items of `postfix` without their spans.
+    fn items(postfix: &[Spanned<Postfix>]) -> Vec<Postfix> {
+        postfix.iter().map(|item| item.node.clone()).collect()
+    }
+
+    /// Evaluates an expression made only of literals.
+    fn evaluate(expression: &str) -> Result<Value, Spanned<Code>> {
+        let postfix = shunting_yard(&lex(expression))?;
+
+        postfix_stack_evaluator(&postfix, |lookup, _| {
+            panic!("nothing to look up: {lookup:?}")
+        })
+    }
+
     #[test]
     fn shutting_yard_algo() {
         let expression = "(5*4+3*2)-1";
-        let tokens = Lexer::lex(expression);
-        let memory = Memoria::new();
-        let result = shunting_yard(tokens, &memory).unwrap();
+        let result = shunting_yard(&lex(expression)).unwrap();
 
         assert_eq!(
-            result,
+            items(&result),
             vec![
-                Token::Numero(5.0, true),
-                Token::Numero(4.0, true),
-                Token::Multiplicacion,
-                Token::Numero(3.0, true),
-                Token::Numero(2.0, true),
-                Token::Multiplicacion,
-                Token::Suma,
-                Token::Numero(1.0, true),
-                Token::Resta,
+                Postfix::Operand(Value::Entero(5)),
+                Postfix::Operand(Value::Entero(4)),
+                Postfix::Operator(Operator::Multiplicacion),
+                Postfix::Operand(Value::Entero(3)),
+                Postfix::Operand(Value::Entero(2)),
+                Postfix::Operator(Operator::Multiplicacion),
+                Postfix::Operator(Operator::Suma),
+                Postfix::Operand(Value::Entero(1)),
+                Postfix::Operator(Operator::Resta),
             ]
-        )
+        );
     }
 
     #[test]
-    fn postfix_arithmetic() {
-        let expression = "(5*4+3*2)-1";
-        let tokens = Lexer::lex(expression);
-        let memory = Memoria::new();
+    fn lookups() {
+        let result = shunting_yard(&lex("a + Mayor(b, 2) * v[i + 1]")).unwrap();
+        let variable = |name: &str| Postfix::Lookup(Lookup::Variable(name.to_string()));
 
-        let postfix = shunting_yard(tokens, &memory).unwrap();
-        let result = postfix_stack_evaluator(postfix);
+        assert_eq!(result[0].node, variable("a"));
+        let Postfix::Lookup(Lookup::Call(name, arguments)) = &result[1].node else {
+            panic!("expected a call, got {:?}", result[1]);
+        };
+        assert_eq!(name, "Mayor");
+        assert_eq!(
+            arguments
+                .iter()
+                .map(|argument| items(argument))
+                .collect::<Vec<_>>(),
+            vec![
+                vec![variable("b")],
+                vec![Postfix::Operand(Value::Entero(2))]
+            ]
+        );
+        let Postfix::Lookup(Lookup::Element(name, indices)) = &result[2].node else {
+            panic!("expected an array element, got {:?}", result[2]);
+        };
+        assert_eq!(name, "v");
+        assert_eq!(
+            items(&indices[0]),
+            vec![
+                variable("i"),
+                Postfix::Operand(Value::Entero(1)),
+                Postfix::Operator(Operator::Suma),
+            ]
+        );
+        assert_eq!(
+            items(&result[3..]),
+            vec![
+                Postfix::Operator(Operator::Multiplicacion),
+                Postfix::Operator(Operator::Suma),
+            ]
+        );
+
+        // a call or an element spans from its name to the closing bracket
+        let spans: Vec<(usize, usize)> = result
+            .iter()
+            .map(|item| (item.span.column, item.span.end - item.span.start))
+            .collect();
+        assert_eq!(spans, vec![(1, 1), (5, 11), (19, 8), (17, 1), (3, 1)]);
+
+        let mut looked_up = Vec::new();
+        let result = postfix_stack_evaluator(&result, |lookup, _| {
+            looked_up.push(lookup.clone());
+            Ok(Value::Entero(2))
+        });
+        assert_eq!(result.ok(), Some(Value::Entero(6)));
+        assert_eq!(looked_up.len(), 3);
+    }
 
-        assert_eq!(result, Some(Token::Numero(25.0, true)));
+    #[test]
+    fn postfix_arithmetic() {
+        assert_eq!(evaluate("(5*4+3*2)-1").ok(), Some(Value::Entero(25)));
     }
 
     #[test]
     fn postfix_concatenate() {
-        let expression = "'hola' + ' mundo'";
-        let tokens = Lexer::lex(expression);
-        let memory = Memoria::new();
-
-        let postfix = shunting_yard(tokens, &memory).unwrap();
-        let result = postfix_stack_evaluator(postfix);
-
-        assert_eq!(result, Some(Token::String("hola mundo".to_string())));
+        assert_eq!(
+            evaluate("'hola' + ' mundo'").ok(),
+            Some(Value::Caracter("hola mundo".to_string()))
+        );
     }
 
     #[test]
     fn postfix_boolean() {
-        let expression = "VERDADERO Y VERDADERO";
-        let tokens = Lexer::lex(expression);
-        let memory = Memoria::new();
-
-        let postfix = shunting_yard(tokens, &memory).unwrap();
-        let result = postfix_stack_evaluator(postfix);
-
-        assert_eq!(result, Some(Token::Boolean(true)))
+        assert_eq!(
+            evaluate("VERDADERO Y VERDADERO").ok(),
+            Some(Value::Logico(true))
+        );
     }
 
     #[test]
     fn postfix_error() {
         let invalid_expressions = vec!["'hola' - 10", "'hola' - 'chau'", "10 - 'hola'"];
         for expr in invalid_expressions {
-            let tokens = Lexer::lex(expr);
-            let memory = Memoria::new();
+            // Should fail when adding 2 different types
+            let result = evaluate(expr);
+
+            assert!(matches!(
+                result,
+                Err(Spanned {
+                    node: Code {
+                        error: PossibleErrors::TypeMismatch
+                    },
+                    ..
+                })
+            ));
+        }
+    }
 
-            let postfix = shunting_yard(tokens, &memory).unwrap();
+    #[test]
+    fn integers_and_reals() {
+        let evaluate = |expression: &str| evaluate(expression).unwrap();
+
+        assert_eq!(evaluate("16777216 + 1"), Value::Entero(16_777_217));
+        assert_eq!(evaluate("2 * 3 - 1"), Value::Entero(5));
+        assert_eq!(evaluate("1.5 * 2"), Value::Real(3.0));
+        assert_eq!(evaluate("2 + 0.5"), Value::Real(2.5));
+        assert_eq!(evaluate("7 / 2"), Value::Real(3.5));
+        assert_eq!(evaluate("4 / 2"), Value::Real(2.0));
+        assert_eq!(
+            evaluate("9223372036854775807 + 0.0").as_real(),
+            Some(9.223_372_036_854_776e18)
+        );
+        assert_eq!(evaluate("3 = 3.0"), Value::Logico(true));
+    }
 
-            // Should return None when adding 2 different types
-            let result = postfix_stack_evaluator(postfix);
+    #[test]
+    fn integer_overflow() {
+        let message = |expression: &str| evaluate(expression).unwrap_err().node.to_string();
 
-            assert_eq!(result, None);
-        }
+        assert_eq!(
+            message("9223372036854775807 + 1"),
+            "ERROR: El resultado no entra en un Entero."
+        );
+        assert_eq!(
+            message("-9223372036854775807 - 2"),
+            "ERROR: El resultado no entra en un Entero."
+        );
+        assert_eq!(
+            message("4294967296 * 4294967296"),
+            "ERROR: El resultado no entra en un Entero."
+        );
+        // the error points at the operator that overflowed
+        let error = evaluate("1 + 9223372036854775807 * 2").unwrap_err();
+        assert_eq!(error.span.column, 25);
+        assert_eq!(
+            evaluate("9223372036854775807 / 1").ok(),
+            Some(Value::Real(9.223_372_036_854_776e18))
+        );
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        let messages: Vec<String> = [
+            "-", "3 *", "* 3", "(1 + 2", "1 + 2)", "()", "1 2", "4 / 0", "F(1,)", "v[1",
+        ]
+        .into_iter()
+        .map(|expression| evaluate(expression).unwrap_err().node.to_string())
+        .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "ERROR 224: Falta operando (despues de -).",
+                "ERROR 224: Falta operando (despues de *).",
+                "ERROR 234: Falta operando (antes de *).",
+                "ERROR: Falta cerrar paréntesis.",
+                "ERROR: Paréntesis de cierre sin abrir.",
+                "ERROR: Falta una expresión.",
+                "ERROR -1: Error de sintaxis.",
+                "ERROR: División por cero.",
+                "ERROR: Falta una expresión.",
+                "ERROR: Falta ].",
+            ]
+        );
+
+        assert_eq!(evaluate("2 * -3 + 1").ok(), Some(Value::Entero(-5)));
+        assert_eq!(evaluate("-(2 + 3)").ok(), Some(Value::Entero(-5)));
+    }
+
+    #[test]
+    fn error_spans() {
+        let column = |expression: &str| evaluate(expression).unwrap_err().span.column;
+
+        assert_eq!(column("'hola' - 10"), 8);
+        assert_eq!(column("10 / (2 - 2)"), 4);
+        assert_eq!(column("1 + 2 *"), 7);
+        assert_eq!(column("1 + * 2"), 5);
+        assert_eq!(column("(1 + 2"), 1);
+        assert_eq!(column("1 + (2 3)"), 8);
+        assert_eq!(column("v[1] + w[2"), 9);
     }
 }
@@ -22,7 +22,8 @@ pub enum Token {
     Multiplicacion,
     Division,
 
-    Numero(f32, bool),
+    Entero(i64),
+    Real(f64),
     String(String),
     Boolean(bool),
 
@@ -38,9 +39,12 @@ pub enum Token {
     Grupo(Vec<Token>),
     AbrirParentesis,
     CerrarParentesis,
+    AbrirCorchete,
+    CerrarCorchete,
+    DosPuntos,
 }
 
-#[derive(Default, Debug, EnumIter, PartialEq, Clone)]
+#[derive(Default, Debug, EnumIter, PartialEq, Clone, Copy)]
 pub enum Keyword {
     // default trait needed for EnumIter
     #[default]
@@ -53,9 +57,42 @@ pub enum Keyword {
     Leer,
     Como,
     Definir,
+    Dimension,
 
     Si,
+    Sino,
     FinSi,
+
+    Mientras,
+    Hacer,
+    FinMientras,
+
+    Repetir,
+    Hasta,
+    HastaQue,
+    MientrasQue,
+
+    Para,
+    Con,
+    ConPaso,
+    FinPara,
+    ParaCada,
+    De,
+
+    Segun,
+    DeOtroModo,
+    FinSegun,
+
+    SubProceso,
+    FinSubProceso,
+    Funcion,
+    FinFuncion,
+    Por,
+    PorValor,
+    PorReferencia,
+    Sin,
+    SinSaltar,
+    SinBajar,
 }
 
 #[derive(Default, Debug, EnumIter, PartialEq, Clone, Copy)]
@@ -70,6 +107,20 @@ pub enum Type {
     Entero,
 }
 
+/// Keywords written as more than one word: the first word, the words that must follow it,
+/// and the keyword they make up together. Following words are matched case-insensitively.
+pub const COMPOUND_KEYWORDS: &[(Keyword, &[&str], Keyword)] = &[
+    (Keyword::Hasta, &["Que"], Keyword::HastaQue),
+    (Keyword::Mientras, &["Que"], Keyword::MientrasQue),
+    (Keyword::Con, &["Paso"], Keyword::ConPaso),
+    (Keyword::Para, &["Cada"], Keyword::ParaCada),
+    (Keyword::De, &["Otro", "Modo"], Keyword::DeOtroModo),
+    (Keyword::Por, &["Valor"], Keyword::PorValor),
+    (Keyword::Por, &["Referencia"], Keyword::PorReferencia),
+    (Keyword::Sin, &["Saltar"], Keyword::SinSaltar),
+    (Keyword::Sin, &["Bajar"], Keyword::SinBajar),
+];
+
 /// Converts to keyword if it is one, or returns a variable
 pub fn convert_to_keyword(text: String) -> Token {
     let lower = text.to_lowercase();
@@ -78,6 +129,10 @@ pub fn convert_to_keyword(text: String) -> Token {
         return Token::Boolean(true);
     } else if lower == "falso" {
         return Token::Boolean(false);
+    } else if lower == "y" {
+        return Token::Y;
+    } else if lower == "o" {
+        return Token::O;
     }
 
     for keyword in Keyword::iter() {
@@ -95,6 +150,13 @@ pub fn convert_to_keyword(text: String) -> Token {
     Token::Variable(text)
 }
 
+/// The form variable and subproceso names are looked up by, `Suma`, `suma` and `SUMA`
+/// all name the same thing.
+pub fn identifier_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+#[allow(dead_code)]
 pub fn convert_to_type(token: &Token) -> Option<Type> {
     match token {
         Token::Tipo(val) => Some(*val),
@@ -102,22 +164,6 @@ pub fn convert_to_type(token: &Token) -> Option<Type> {
     }
 }
 
-impl Token {
-    pub fn get_as_string(self) -> String {
-        match self {
-            Token::Numero(num, rounded) => {
-                if rounded {
-                    return f32::trunc(num).to_string();
-                }
-                num.to_string()
-            }
-            Token::String(string) => string,
-            Token::Boolean(bool) => bool.to_string(),
-            _ => String::new(),
-        }
-    }
-}
-
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let name = format!("{self:?}");
@@ -131,6 +177,13 @@ impl fmt::Display for Token {
 
 impl fmt::Display for Keyword {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((first, words, _)) = COMPOUND_KEYWORDS
+            .iter()
+            .find(|(_, _, compound)| compound == self)
+        {
+            return write!(f, "{first} {}", words.join(" "));
+        }
+
         let name = format!("{self:?}");
         let split = name.split('(').collect::<Vec<&str>>();
 
@@ -161,6 +214,15 @@ mod tests {
         assert_eq!(igual, "Igual".to_string());
     }
 
+    #[test]
+    fn compound_keyword() {
+        assert_eq!(Keyword::HastaQue.to_string(), "Hasta Que".to_string());
+        assert_eq!(
+            convert_to_keyword("hastaque".to_string()),
+            Token::Variable("hastaque".to_string())
+        );
+    }
+
     #[test]
     fn identificador() {
         let identificador = Token::Identificador("Definir".to_string()).to_string();
@@ -0,0 +1,191 @@
+use core::fmt;
+
+use crate::tokens::{Token, Type};
+
+/// A value the algorithm works with while running, stored in `Memoria` and produced
+/// by evaluating expressions. Expressions only ever produce the first four.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Entero(i64),
+    Real(f64),
+    Logico(bool),
+    Caracter(String),
+    /// The elements of a `Dimension`ed array in row-major order, each `None` until it's
+    /// assigned when variables don't start with a default.
+    Arreglo {
+        dimensions: Vec<usize>,
+        values: Vec<Option<Value>>,
+    },
+    /// An alias to a variable of an older frame, or to one of its elements.
+    Referencia(Reference),
+}
+
+/// Where an aliased value lives, it never points at another reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub frame: usize,
+    pub name: String,
+    /// The position of the element in row-major order when it aliases one.
+    pub element: Option<usize>,
+}
+
+impl Value {
+    /// The value a variable of type `tipo` starts with.
+    pub fn initial(tipo: Type) -> Option<Value> {
+        // default values straight from PSeInt
+        match tipo {
+            Type::Entero => Some(Value::Entero(0)),
+            Type::Real => Some(Value::Real(0.0)),
+            Type::Logico => Some(Value::Logico(false)),
+            Type::Caracter => Some(Value::Caracter(String::new())),
+            Type::None => None,
+        }
+    }
+
+    /// The value of a literal written in the source, `None` for any other token.
+    pub fn from_literal(token: &Token) -> Option<Value> {
+        match token {
+            Token::Entero(num) => Some(Value::Entero(*num)),
+            Token::Real(num) => Some(Value::Real(*num)),
+            Token::Boolean(bool) => Some(Value::Logico(*bool)),
+            Token::String(string) => Some(Value::Caracter(string.clone())),
+            _ => None,
+        }
+    }
+
+    /// The type a variable needs to hold the value. Arrays and references have none of
+    /// their own, it's the one their elements or the aliased variable were declared with.
+    pub fn tipo(&self) -> Type {
+        match self {
+            Value::Entero(_) => Type::Entero,
+            Value::Real(_) => Type::Real,
+            Value::Logico(_) => Type::Logico,
+            Value::Caracter(_) => Type::Caracter,
+            Value::Arreglo { .. } | Value::Referencia(_) => Type::None,
+        }
+    }
+
+    /// The name of the value's type, for error messages.
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Arreglo { .. } => "Arreglo".to_string(),
+            Value::Referencia(_) => "Referencia".to_string(),
+            value => value.tipo().to_string(),
+        }
+    }
+
+    /// The value of an Entero or a Real as a Real.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Value::Entero(num) => Some(*num as f64),
+            Value::Real(num) => Some(*num),
+            _ => None,
+        }
+    }
+
+    /// The value of an Entero, or of a Real without decimals such as `4 / 2`, as an Entero.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub fn as_integer(&self) -> Option<i64> {
+        let integers = i64::MIN as f64..i64::MAX as f64;
+
+        match self {
+            Value::Entero(num) => Some(*num),
+            Value::Real(num) if num.fract() == 0.0 && integers.contains(num) => Some(*num as i64),
+            _ => None,
+        }
+    }
+
+    /// Converts the value so it can be stored in a variable declared as `tipo`, giving it
+    /// back when it doesn't fit. An Entero widens to a Real and a Real fits in an Entero
+    /// when `as_integer` allows it. A `Type::None` variable takes any value.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn convert(self, tipo: Type) -> Result<Value, Value> {
+        match (tipo, self) {
+            (Type::None, value) => Ok(value),
+            (Type::Real, Value::Entero(num)) => Ok(Value::Real(num as f64)),
+            (Type::Entero, value @ Value::Real(_)) => {
+                value.as_integer().map(Value::Entero).ok_or(value)
+            }
+            (tipo, value) if value.tipo() == tipo => Ok(value),
+            (_, value) => Err(value),
+        }
+    }
+}
+
+/// Values are written the way `Escribir` shows them.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Entero(num) => write!(f, "{num}"),
+            Value::Real(num) => write!(f, "{num}"),
+            Value::Logico(true) => write!(f, "VERDADERO"),
+            Value::Logico(false) => write!(f, "FALSO"),
+            Value::Caracter(string) => write!(f, "{string}"),
+            // elements that weren't assigned are left blank
+            Value::Arreglo { values, .. } => {
+                let elements: Vec<String> = values
+                    .iter()
+                    .map(|value| value.as_ref().map(Value::to_string).unwrap_or_default())
+                    .collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Referencia(reference) => write!(f, "Referencia a {}", reference.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod value_tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let written: Vec<String> = [
+            Value::Entero(-3),
+            Value::Real(2.5),
+            Value::Real(4.0),
+            Value::Logico(true),
+            Value::Logico(false),
+            Value::Caracter("hola mundo".to_string()),
+        ]
+        .iter()
+        .map(Value::to_string)
+        .collect();
+
+        assert_eq!(
+            written,
+            vec!["-3", "2.5", "4", "VERDADERO", "FALSO", "hola mundo"]
+        );
+    }
+    #[test]
+    fn conversions() {
+        assert_eq!(Value::Entero(3).convert(Type::Real), Ok(Value::Real(3.0)));
+        assert_eq!(Value::Real(4.0).convert(Type::Entero), Ok(Value::Entero(4)));
+        assert_eq!(
+            Value::Real(2.5).convert(Type::Entero),
+            Err(Value::Real(2.5))
+        );
+        assert_eq!(
+            Value::Real(1e30).convert(Type::Entero),
+            Err(Value::Real(1e30))
+        );
+        assert_eq!(
+            Value::Logico(true).convert(Type::None),
+            Ok(Value::Logico(true))
+        );
+        assert_eq!(
+            Value::Caracter("3".to_string()).convert(Type::Entero),
+            Err(Value::Caracter("3".to_string()))
+        );
+
+        let array = Value::Arreglo {
+            dimensions: vec![2],
+            values: vec![Some(Value::Entero(1)), None],
+        };
+        assert_eq!(array.type_name(), "Arreglo");
+        assert_eq!(array.to_string(), "[1, ]");
+        assert_eq!(array.clone().convert(Type::Entero), Err(array));
+        assert_eq!(Value::Real(0.5).type_name(), "Real");
+    }
+}